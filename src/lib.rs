@@ -0,0 +1,5768 @@
+/**
+ * s3-file-upload library, the local file scanning, checksums and bucket operations behind the command line tool
+ */
+
+extern crate rusoto;
+extern crate hyper;
+extern crate hyper_native_tls;
+extern crate native_tls;
+extern crate libc;
+extern crate flate2;
+extern crate base64;
+extern crate crypto;
+extern crate term_painter;
+
+use std::env;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::path::PathBuf;
+use std::fs::{File, OpenOptions, remove_file};
+use std::io::{self, Read, Write};
+use std::clone::Clone;
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use std::sync::{Arc, Mutex, Condvar};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use rusoto::{AwsCredentials, CredentialsError, DispatchSignedRequest, EnvironmentProvider, HttpDispatchError, HttpResponse, SignedRequest, ProfileProvider, ProvideAwsCredentials, Region};
+use rusoto::s3::{Object, S3Client, ListObjectsV2Request, ListObjectsV2Output, ListObjectsV2Error, GetObjectRequest, GetObjectOutput, GetObjectError, PutObjectRequest, PutObjectOutput, PutObjectError, DeleteObjectsRequest, DeleteObjectsOutput, DeleteObjectsError, Delete, ObjectIdentifier};
+use rusoto::s3::{CreateMultipartUploadRequest, CreateMultipartUploadOutput, CreateMultipartUploadError, UploadPartRequest, UploadPartOutput, UploadPartError, CompleteMultipartUploadRequest, CompleteMultipartUploadOutput, CompleteMultipartUploadError, CompletedMultipartUpload, CompletedPart};
+use rusoto::s3::{AbortMultipartUploadRequest, AbortMultipartUploadOutput, AbortMultipartUploadError, HeadBucketRequest, HeadBucketError, GetBucketLocationRequest};
+use rusoto::sts::{StsClient, AssumeRoleRequest, GetCallerIdentityRequest, NewAwsCredsForStsCreds};
+
+use hyper::header::Headers;
+use hyper::method::Method;
+use hyper::client::{ProxyConfig, RedirectPolicy};
+use hyper::net::{HttpConnector, HttpsConnector};
+
+use hyper_native_tls::NativeTlsClient;
+use native_tls::{Certificate, TlsConnector};
+
+use flate2::Compression;
+use flate2::write::GzEncoder;
+use flate2::read::GzDecoder;
+
+use crypto::md5::Md5;
+use crypto::sha2::Sha256;
+use crypto::digest::Digest;
+
+use term_painter::ToStyle;
+use term_painter::Color::*;
+
+/**
+ * Number of times an upload request is retried after a transient error, unless the max_retries parameter is set
+ */
+pub const MAX_RETRIES: u32 = 3;
+
+/**
+ * Default number of seconds a request waits for its connection to send or receive data before failing
+ */
+pub const HTTP_TIMEOUT: u64 = 300;
+
+/**
+ * Size of the chunks an upload body is sent in when the bandwidth is limited
+ */
+const BANDWIDTH_CHUNK_SIZE: usize = 16 * 1024;
+
+/**
+ * Delay in milliseconds before the first retry of an upload request, doubled for each retry after it
+ */
+const RETRY_BASE_DELAY: u64 = 500;
+
+/**
+ * Longest delay in milliseconds between retries of an upload request
+ */
+const RETRY_MAX_DELAY: u64 = 20000;
+
+/**
+ * S3 error codes of throttling, server errors and bodies corrupted in transit that are worth retrying
+ */
+const TRANSIENT_ERROR_CODES: [&'static str; 8] = ["BadDigest", "InternalError", "ServiceUnavailable", "SlowDown", "RequestTimeout", "Throttling", "ThrottlingException", "RequestLimitExceeded"];
+
+/**
+ * Canned ACLs S3 accepts for uploaded objects
+ */
+pub const CANNED_ACLS: [&'static str; 7] = ["private", "public-read", "public-read-write", "authenticated-read", "aws-exec-read", "bucket-owner-read", "bucket-owner-full-control"];
+
+/**
+ * Canned ACLs that let anyone, or any AWS account, read the uploaded objects
+ */
+pub const PUBLIC_ACLS: [&'static str; 3] = ["public-read", "public-read-write", "authenticated-read"];
+
+/**
+ * Default patterns of files that usually hold secrets, matched like ignore patterns
+ */
+pub const SENSITIVE_PATTERNS: [&'static str; 14] = [".env", ".env.*", "id_rsa", "id_dsa", "id_ecdsa", "id_ed25519", "*.pem", "*.key", "*.p12", "*.pfx", "credentials", ".git-credentials", ".npmrc", ".htpasswd"];
+
+/**
+ * Storage classes S3 accepts for uploaded objects
+ */
+pub const STORAGE_CLASSES: [&'static str; 8] = ["STANDARD", "REDUCED_REDUNDANCY", "STANDARD_IA", "ONEZONE_IA", "INTELLIGENT_TIERING", "GLACIER", "GLACIER_IR", "DEEP_ARCHIVE"];
+
+/**
+ * Default size in bytes above which files are uploaded in parts
+ */
+pub const MULTIPART_THRESHOLD: u64 = 100 * 1024 * 1024;
+
+/**
+ * Smallest size in bytes of the parts of a multipart upload
+ */
+const MULTIPART_PART_SIZE: u64 = 8 * 1024 * 1024;
+
+/**
+ * Largest number of keys S3 accepts in one DeleteObjects request
+ */
+const DELETE_BATCH_SIZE: usize = 1000;
+
+/**
+ * Largest number of tags S3 accepts on an object
+ */
+const MAX_TAGS: usize = 10;
+
+/**
+ * Largest number of parts S3 accepts for a multipart upload
+ */
+const MULTIPART_MAX_PARTS: u64 = 10000;
+
+/**
+ * Size in bytes of the chunks read from a file while computing its checksum
+ */
+const HASH_CHUNK_SIZE: usize = 64 * 1024;
+
+/**
+ * Start of the checksums file line recording the hash algorithm of the checksums
+ */
+const CHECKSUMS_HASH_HEADER: &'static str = "# hash: ";
+
+/**
+ * Start of the local journal line recording the bucket and prefix the files were uploaded to
+ */
+const JOURNAL_BUCKET_HEADER: &'static str = "# bucket: ";
+
+/**
+ * Number of bytes read from the start of a file to detect its mime type from its contents
+ */
+const MIME_SNIFF_SIZE: usize = 512;
+
+/**
+ * Session name of the assumed role, shown in CloudTrail for the requests made with its credentials
+ */
+const ASSUMED_ROLE_SESSION_NAME: &'static str = "s3-file-upload";
+
+/**
+ * Number of seconds the credentials of an assumed role are requested for
+ */
+const ASSUMED_ROLE_DURATION: u64 = 3600;
+
+/**
+ * Number of seconds before the credentials of an assumed role expire that new ones are requested
+ */
+const ASSUMED_ROLE_REFRESH_MARGIN: u64 = 300;
+
+/**
+ * Host of the CloudFront API, which has a single global endpoint signed for us-east-1
+ */
+const CLOUDFRONT_HOST: &'static str = "cloudfront.amazonaws.com";
+
+/**
+ * Largest number of paths invalidated one by one, above it the whole prefix is invalidated with a wildcard instead
+ */
+const CLOUDFRONT_MAX_PATHS: usize = 3000;
+
+/**
+ * Enum for file status
+ */
+#[derive(PartialEq)]
+pub enum FileStatus
+{
+    NotModified,
+    Modified,
+    New
+}
+
+/**
+ * Enum for the format of the output of a run
+ */
+#[derive(Clone, Copy, PartialEq)]
+pub enum OutputFormat
+{
+    Text,
+    Json,
+    Github,
+    Quiet
+}
+
+/**
+ * Enum for the hash algorithm of the file checksums
+ */
+#[derive(Clone, Copy, PartialEq)]
+pub enum HashAlgorithm
+{
+    Md5,
+    Sha256
+}
+
+/**
+ * Get the name of a hash algorithm, as used by the hash parameter and the checksums file
+ */
+pub fn hash_algorithm_name(algorithm: HashAlgorithm) -> &'static str
+{
+    return match algorithm
+    {
+        HashAlgorithm::Md5 => "md5",
+        HashAlgorithm::Sha256 => "sha256"
+    };
+}
+
+/**
+ * Get the number of hex characters in a checksum of a hash algorithm
+ */
+fn hash_algorithm_hex_length(algorithm: HashAlgorithm) -> usize
+{
+    return match algorithm
+    {
+        HashAlgorithm::Md5 => 32,
+        HashAlgorithm::Sha256 => 64
+    };
+}
+
+/**
+ * Get the hash algorithm for a name, as used by the hash parameter and the checksums file
+ */
+pub fn parse_hash_algorithm(name: &str) -> Option<HashAlgorithm>
+{
+    return match name.to_lowercase().as_ref()
+    {
+        "md5" => Some(HashAlgorithm::Md5),
+        "sha256" => Some(HashAlgorithm::Sha256),
+        _ => None
+    };
+}
+
+/**
+ * Create a new digest for a hash algorithm
+ */
+fn hash_algorithm_digest(algorithm: HashAlgorithm) -> Box<dyn Digest + Send>
+{
+    return match algorithm
+    {
+        HashAlgorithm::Md5 => Box::new(Md5::new()),
+        HashAlgorithm::Sha256 => Box::new(Sha256::new())
+    };
+}
+
+/**
+ * Enum for the line format of the checksums file
+ */
+#[derive(Clone, Copy, PartialEq)]
+pub enum ChecksumsFormat
+{
+    Path,
+    Md5sum
+}
+
+/**
+ * Get the line format of the checksums file for a name, as used by the checksums_format parameter
+ */
+pub fn parse_checksums_format(name: &str) -> Option<ChecksumsFormat>
+{
+    return match name.to_lowercase().as_ref()
+    {
+        "path" => Some(ChecksumsFormat::Path),
+        "md5sum" => Some(ChecksumsFormat::Md5sum),
+        _ => None
+    };
+}
+
+/**
+ * Escape a path for a GNU md5sum line, which marks a line with an escaped path with a leading backslash
+ */
+fn md5sum_escape_path(path: &str) -> (bool, String)
+{
+    let escaped_path = path.replace("\\", "\\\\").replace("\n", "\\n").replace("\r", "\\r");
+
+    return (escaped_path != path, escaped_path);
+}
+
+/**
+ * Unescape the path of a GNU md5sum line that starts with a backslash
+ */
+fn md5sum_unescape_path(path: &str) -> String
+{
+    let mut unescaped_path = String::new();
+    let mut characters = path.chars();
+
+    while let Some(character) = characters.next()
+    {
+        if character != '\\'
+        {
+            unescaped_path.push(character);
+            continue;
+        }
+
+        match characters.next()
+        {
+            Some('n') => unescaped_path.push('\n'),
+            Some('r') => unescaped_path.push('\r'),
+            Some(escaped_character) => unescaped_path.push(escaped_character),
+            None => unescaped_path.push('\\')
+        }
+    }
+
+    return unescaped_path;
+}
+
+/**
+ * Struct for files
+ */
+#[derive(Clone)]
+pub struct LocalFile
+{
+    pub path: PathBuf,
+    pub checksum: String,
+    pub mtime: Option<u64>
+}
+
+/**
+ * Enum for the errors of an upload
+ */
+pub enum UploadError
+{
+    Io(io::Error),
+    Request(String)
+}
+
+impl fmt::Display for UploadError
+{
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result
+    {
+        match *self
+        {
+            UploadError::Io(ref error) => write!(formatter, "{}", error),
+            UploadError::Request(ref message) => write!(formatter, "{}", message)
+        }
+    }
+}
+
+impl From<io::Error> for UploadError
+{
+    fn from(error: io::Error) -> UploadError
+    {
+        return UploadError::Io(error);
+    }
+}
+
+/**
+ * Struct for a rule overriding the options of the uploaded files that match its glob pattern
+ */
+#[derive(Clone)]
+pub struct UploadRule
+{
+    pub pattern: String,
+    pub acl: Option<String>,
+    pub cache_control: Option<String>,
+    pub content_type: Option<String>
+}
+
+/**
+ * Struct for the options applied to each uploaded file
+ */
+#[derive(Clone)]
+pub struct UploadOptions
+{
+    pub prefix: String,
+    pub acl: String,
+    pub cache_control: Option<String>,
+    pub gzip: bool,
+    pub storage_class: Option<String>,
+    pub server_side_encryption: Option<String>,
+    pub ssekms_key_id: Option<String>,
+    pub headers: Vec<(String, String)>,
+    pub mime_overrides: HashMap<String, String>,
+    pub sniff_mime: bool,
+    pub hash_algorithm: HashAlgorithm,
+    pub checksums_format: ChecksumsFormat,
+    pub multipart_threshold: u64,
+    pub max_retries: u32,
+    pub checksums_tagging: Option<String>,
+    pub strip_components: usize,
+    pub rules: Vec<UploadRule>
+}
+
+/**
+ * Get the mime type for a local file
+ */
+fn local_file_get_mime(file: &LocalFile, mime_overrides: &HashMap<String, String>, sniff_mime: bool) -> String
+{
+    // Files without an extension, e.g. LICENSE or Dockerfile, fall through to the default type
+    let extension = file.path.extension().and_then(|extension| extension.to_str()).unwrap_or("").to_lowercase();
+
+    // Custom mime types from the mime_overrides parameter take precedence over the built in types
+    if let Some(mime_type) = mime_overrides.get(&extension)
+    {
+        return mime_type.to_owned();
+    }
+
+    // Extensions are lowercased first, so a new type only needs a lowercase arm here
+    let mime_type = match extension.as_ref()
+    {
+        "html" | "htm"  => "text/html",
+        "css"           => "text/css",
+        "js"            => "application/javascript",
+        "json"          => "application/json",
+        "xml"           => "application/xml",
+        "txt"           => "text/plain",
+        "png"           => "image/png",
+        "gif"           => "image/gif",
+        "jpg" | "jpeg"  => "image/jpeg",
+        "svg"           => "image/svg+xml",
+        "ico"           => "image/x-icon",
+        "webp"          => "image/webp",
+        "woff"          => "font/woff",
+        "woff2"         => "font/woff2",
+        "ttf"           => "font/ttf",
+        "pdf"           => "application/pdf",
+        "mp4"           => "video/mp4",
+        "webm"          => "video/webm",
+        "wasm"          => "application/wasm",
+        _               => "application/octet-stream",
+    };
+
+    // Files with an unknown extension are detected from their first bytes if the sniff_mime parameter is set
+    if sniff_mime && mime_type == "application/octet-stream"
+    {
+        if let Some(mime_type) = local_file_sniff_mime(file)
+        {
+            return mime_type.to_owned();
+        }
+    }
+
+    return mime_type.to_owned();
+}
+
+/**
+ * Get the mime type of a local file from the magic numbers at the start of its contents
+ */
+fn local_file_sniff_mime(file: &LocalFile) -> Option<&'static str>
+{
+    let mut buffer = [0u8; MIME_SNIFF_SIZE];
+    let mut bytes_read = 0;
+
+    // Only the start of the file is read, an unreadable file is left to fail when it is uploaded
+    let mut file_handle = match File::open(&file.path)
+    {
+        Ok(file_handle) => file_handle,
+        Err(_) => return None
+    };
+
+    while bytes_read < MIME_SNIFF_SIZE
+    {
+        match file_handle.read(&mut buffer[bytes_read..])
+        {
+            Ok(0) => break,
+            Ok(count) => bytes_read += count,
+            Err(ref error) if error.kind() == io::ErrorKind::Interrupted => continue,
+            Err(_) => return None
+        }
+    }
+
+    let contents = &buffer[..bytes_read];
+
+    if contents.starts_with(b"\x89PNG\r\n\x1a\n")
+    {
+        return Some("image/png");
+    }
+
+    if contents.starts_with(b"\xff\xd8\xff")
+    {
+        return Some("image/jpeg");
+    }
+
+    if contents.starts_with(b"GIF87a") || contents.starts_with(b"GIF89a")
+    {
+        return Some("image/gif");
+    }
+
+    if contents.starts_with(b"%PDF-")
+    {
+        return Some("application/pdf");
+    }
+
+    if contents.len() >= 12 && &contents[..4] == b"RIFF" && &contents[8..12] == b"WEBP"
+    {
+        return Some("image/webp");
+    }
+
+    if contents.starts_with(b"\0asm")
+    {
+        return Some("application/wasm");
+    }
+
+    // Markup may follow a byte order mark and whitespace, and its tags are matched case-insensitively
+    let text = String::from_utf8_lossy(contents.strip_prefix(b"\xef\xbb\xbf").unwrap_or(contents)).trim_start().to_lowercase();
+
+    if text.starts_with("<!doctype html") || text.starts_with("<html") || text.starts_with("<head") || text.starts_with("<body")
+    {
+        return Some("text/html");
+    }
+
+    if text.starts_with("<svg")
+    {
+        return Some("image/svg+xml");
+    }
+
+    if text.starts_with("<?xml")
+    {
+        return Some("application/xml");
+    }
+
+    return None;
+}
+
+/**
+ * Get the checksum of a local file with a hash algorithm
+ */
+fn local_file_get_hash(file: &LocalFile, algorithm: HashAlgorithm) -> io::Result<String>
+{
+    let mut file_handle = File::open(&file.path)?;
+    let mut digest = hash_algorithm_digest(algorithm);
+    let mut buffer = [0u8; HASH_CHUNK_SIZE];
+
+    // The file is hashed in chunks so memory use doesn't grow with the file size
+    loop
+    {
+        match file_handle.read(&mut buffer)
+        {
+            Ok(0) =>
+            {
+                return Ok(digest.result_str());
+            }
+            Ok(bytes_read) =>
+            {
+                digest.input(&buffer[..bytes_read]);
+            }
+            Err(ref error) if error.kind() == io::ErrorKind::Interrupted =>
+            {
+                continue;
+            }
+            Err(error) =>
+            {
+                return Err(error);
+            }
+        }
+    }
+}
+
+/**
+ * Get the base64 encoded MD5 digest S3 expects in a Content-MD5 header, from a hex MD5 checksum or otherwise (e.g. for a SHA-256 checksum) by hashing the body
+ */
+fn get_content_md5(md5: &str, body: &[u8]) -> String
+{
+    let mut digest: Vec<u8> = (0..md5.len() / 2).filter_map(|index| u8::from_str_radix(&md5[index * 2..index * 2 + 2], 16).ok()).collect();
+
+    if md5.len() != 32 || digest.len() != 16
+    {
+        let mut md5_checksum = Md5::new();
+        md5_checksum.input(body);
+
+        digest = vec![0u8; 16];
+        md5_checksum.result(&mut digest);
+    }
+
+    return base64::encode(&digest);
+}
+
+/**
+ * Struct for a writer that feeds everything written to it into a digest
+ */
+struct DigestWriter
+{
+    digest: Box<dyn Digest + Send>
+}
+
+impl Write for DigestWriter
+{
+    fn write(&mut self, buffer: &[u8]) -> io::Result<usize>
+    {
+        self.digest.input(buffer);
+        return Ok(buffer.len());
+    }
+
+    fn flush(&mut self) -> io::Result<()>
+    {
+        return Ok(());
+    }
+}
+
+/**
+ * Check if a mime type is text based and worth compressing, images and video are already compressed
+ */
+fn mime_type_is_compressible(mime_type: &str) -> bool
+{
+    return mime_type.starts_with("text/") || ["application/javascript", "application/json", "application/xml", "application/wasm", "image/svg+xml"].contains(&mime_type);
+}
+
+/**
+ * Get the gzip compressed contents of a local file
+ */
+fn local_file_get_gzip(file: &LocalFile) -> io::Result<Vec<u8>>
+{
+    let mut file_handle = File::open(&file.path)?;
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::Default);
+
+    io::copy(&mut file_handle, &mut encoder)?;
+
+    return encoder.finish();
+}
+
+/**
+ * Get the checksum of the gzip compressed contents of a local file with a hash algorithm, without holding the compressed contents in memory
+ */
+fn local_file_get_gzip_hash(file: &LocalFile, algorithm: HashAlgorithm) -> io::Result<String>
+{
+    let mut file_handle = File::open(&file.path)?;
+    let mut encoder = GzEncoder::new(DigestWriter { digest: hash_algorithm_digest(algorithm) }, Compression::Default);
+
+    io::copy(&mut file_handle, &mut encoder)?;
+    let mut writer = encoder.finish()?;
+
+    return Ok(writer.digest.result_str());
+}
+
+/**
+ * Get a relative path for a local file, with forward slashes on every platform
+ */
+pub fn local_file_get_relative_path(file: &LocalFile, local_path: &String) -> String
+{
+    // The local path is removed by whole path components, so it can't match again deeper in the tree or inside a file name
+    if let Ok(relative_path) = file.path.strip_prefix(local_path)
+    {
+        return path_normalize_separators(&relative_path.to_string_lossy()).trim_start_matches('/').to_owned();
+    }
+
+    // Windows paths aren't split into components on other platforms, e.g. C:\site\index.html, so the local path is removed from the normalized text instead
+    let path = path_normalize_separators(&file.path.to_string_lossy());
+    let root = path_normalize_separators(local_path);
+    let root = root.trim_end_matches('/');
+
+    if path.starts_with(root) && path[root.len()..].starts_with("/")
+    {
+        return path[root.len()..].trim_start_matches('/').to_owned();
+    }
+
+    return path.trim_start_matches('/').to_owned();
+}
+
+/**
+ * Get the canonical form of a local path, so ./site, ./site/ and /path/to/site all give the same keys
+ */
+pub fn local_path_canonicalize(local_path: &str) -> io::Result<String>
+{
+    let canonical_path = fs::canonicalize(local_path)?;
+
+    // The keys are built from the local path as text, so a path that isn't valid UTF-8 can't be uploaded from
+    return match canonical_path.to_str()
+    {
+        Some(path) => Ok(String::from(path)),
+        None => Err(io::Error::new(io::ErrorKind::InvalidData, format!("\"{}\" isn't valid UTF-8", canonical_path.display())))
+    };
+}
+
+/**
+ * Get a path with forward slashes and without a Windows drive letter or verbatim prefix, e.g. \\?\C:\site\index.html becomes /site/index.html
+ */
+fn path_normalize_separators(path: &str) -> String
+{
+    let path = path.replace("\\", "/");
+    let path = path.trim_start_matches("//?/");
+    let mut characters = path.chars();
+
+    return match (characters.next(), characters.next())
+    {
+        (Some(drive), Some(':')) if drive.is_ascii_alphabetic() => String::from(&path[2..]),
+        _ => String::from(path)
+    };
+}
+
+/**
+ * Get a key prefix ending in a single slash from a prefix parameter value, e.g. assets or /assets/ become assets/
+ */
+pub fn parse_prefix(value: &str) -> String
+{
+    let prefix = value.trim().trim_matches('/');
+
+    if prefix.is_empty()
+    {
+        return String::from("");
+    }
+
+    return format!("{}/", prefix);
+}
+
+/**
+ * Get the path of a local file in the bucket and checksums, its relative path without the first strip_components directories
+ */
+pub fn local_file_get_key_path(file: &LocalFile, local_path: &String, strip_components: usize) -> String
+{
+    let relative_path = local_file_get_relative_path(file, local_path);
+
+    // Files with too few directories are skipped before they are looked at, so they are kept whole here
+    return relative_path.splitn(strip_components + 1, "/").nth(strip_components).map(String::from).unwrap_or(relative_path);
+}
+
+/**
+ * Check if a local file has more directories than the strip_components option removes, so a file name is left for its key
+ */
+pub fn local_file_has_key_path(file: &LocalFile, local_path: &String, strip_components: usize) -> bool
+{
+    return local_file_get_relative_path(file, local_path).split("/").count() > strip_components;
+}
+
+/**
+ * Skip the local files with no more directories than the strip_components option removes, with a warning, and check that no two files are left with the same key path
+ */
+pub fn local_files_strip_components(files: Vec<LocalFile>, local_path: &String, strip_components: usize) -> Result<Vec<LocalFile>, String>
+{
+    let mut kept_files: Vec<LocalFile> = Vec::new();
+    let mut key_paths: HashMap<String, String> = HashMap::new();
+
+    for file in files
+    {
+        let relative_path = local_file_get_relative_path(&file, local_path);
+
+        if !local_file_has_key_path(&file, local_path, strip_components)
+        {
+            eprintln!("{}: skipping \"{}\", nothing is left of its path after --strip-components={}", stderr_paint(Yellow, "Warning"), relative_path, strip_components);
+            continue;
+        }
+
+        let key_path = local_file_get_key_path(&file, local_path, strip_components);
+
+        if let Some(other_path) = key_paths.insert(key_path.clone(), relative_path.clone())
+        {
+            return Err(format!("\"{}\" and \"{}\" would both be uploaded as \"{}\"", other_path, relative_path, key_path));
+        }
+
+        kept_files.push(file);
+    }
+
+    return Ok(kept_files);
+}
+
+/**
+ * Get the bucket key for a local file under the key prefix of the upload options
+ */
+pub fn local_file_get_key(file: &LocalFile, local_path: &String, options: &UploadOptions) -> String
+{
+    return format!("{}{}", options.prefix, local_file_get_key_path(file, local_path, options.strip_components));
+}
+
+/**
+ * Describe the body of an S3 error response by its code and message, with a hint on how to fix the common misconfigurations
+ */
+fn error_body_describe(body: &str) -> String
+{
+    let code = match xml_element_text(body, "Code")
+    {
+        Some(code) => code,
+        None => return String::from(body)
+    };
+
+    let message = xml_element_text(body, "Message").unwrap_or(String::from(""));
+
+    let hint = match code.as_str()
+    {
+        "AccessDenied" | "AllAccessDisabled" =>
+            "check that the credentials are allowed to access the bucket, and that the bucket accepts the --acl (Block Public Access rejects public ACLs)",
+        "NoSuchBucket" =>
+            "check the bucket name, and that the bucket was created in the --region",
+        "PermanentRedirect" | "AuthorizationHeaderMalformed" | "IllegalLocationConstraintException" =>
+            "the bucket is in another region, check --region",
+        "InvalidAccessKeyId" | "SignatureDoesNotMatch" | "ExpiredToken" | "InvalidToken" | "TokenRefreshRequired" =>
+            "check the credentials of --credentials and --profile, or AWS_ACCESS_KEY_ID and AWS_SECRET_ACCESS_KEY",
+        "SlowDown" | "Throttling" | "ThrottlingException" | "RequestLimitExceeded" =>
+            "S3 is throttling the requests, lower --concurrency or try again later",
+        _ => ""
+    };
+
+    if hint.is_empty()
+    {
+        return format!("{}: {}", code, message);
+    }
+
+    return format!("{}: {} - {}", code, message, hint);
+}
+
+/**
+ * Describe an error sending a request to S3, which never got a response
+ *
+ * The message of the error isn't included, as this rusoto version builds it from the deprecated Error::description, which only says so on current Rust versions
+ */
+fn dispatch_error_describe(_error: &HttpDispatchError) -> String
+{
+    return String::from("S3 could not be reached - check the network, the --endpoint and any proxy, or raise --timeout");
+}
+
+/**
+ * Describe a failed put object request
+ */
+fn put_object_error_describe(error: &PutObjectError) -> String
+{
+    match *error
+    {
+        PutObjectError::HttpDispatch(ref error) => return dispatch_error_describe(error),
+        PutObjectError::Unknown(ref body) => return error_body_describe(body),
+        ref error => return error.to_string()
+    }
+}
+
+/**
+ * Describe a failed list objects request
+ */
+fn list_objects_error_describe(error: &ListObjectsV2Error) -> String
+{
+    match *error
+    {
+        ListObjectsV2Error::HttpDispatch(ref error) => return dispatch_error_describe(error),
+        ListObjectsV2Error::NoSuchBucket(_) => return error_body_describe("<Code>NoSuchBucket</Code><Message>The specified bucket does not exist</Message>"),
+        ListObjectsV2Error::Unknown(ref body) => return error_body_describe(body),
+        ref error => return error.to_string()
+    }
+}
+
+/**
+ * Describe a failed get object request
+ */
+fn get_object_error_describe(error: &GetObjectError) -> String
+{
+    match *error
+    {
+        GetObjectError::HttpDispatch(ref error) => return dispatch_error_describe(error),
+        GetObjectError::Unknown(ref body) => return error_body_describe(body),
+        ref error => return error.to_string()
+    }
+}
+
+/**
+ * Check that a bucket exists and the credentials can access it, before any other work is done
+ */
+pub fn check_bucket_access(client: &dyn ObjectStore, bucket_name: &String) -> Result<(), String>
+{
+    match client.head_bucket(&HeadBucketRequest { bucket: bucket_name.to_owned() })
+    {
+        Ok(_) => return Ok(()),
+        Err(HeadBucketError::HttpDispatch(ref error)) => return Err(dispatch_error_describe(error)),
+        Err(HeadBucketError::Credentials(ref error)) => return Err(format!("the credentials could not be loaded: {}", error)),
+
+        // The response to a HEAD request has no body, so a missing bucket, another region and a denied access can't be told apart
+        Err(_) => return Err(format!("bucket \"{}\" could not be found or accessed - check the bucket name, the --region and that the credentials are allowed to list it, or run with --doctor to find out which", bucket_name))
+    }
+}
+
+/**
+ * Check if the body of an S3 error response has a throttling or server error code
+ */
+fn error_body_is_transient(body: &str) -> bool
+{
+    return TRANSIENT_ERROR_CODES.iter().any(|code| body.contains(&format!("<Code>{}</Code>", code)));
+}
+
+/**
+ * Check if an upload error is transient, a network error or S3 throttling or server error, rather than e.g. access being denied
+ */
+fn upload_error_is_transient(error: &PutObjectError) -> bool
+{
+    match *error
+    {
+        // The dispatch error only carries the deprecated description() message, so a connection reset can't be told apart from other network errors and they are all retried
+        PutObjectError::HttpDispatch(_) => true,
+        PutObjectError::Unknown(ref body) => error_body_is_transient(body),
+        _ => false
+    }
+}
+
+/**
+ * Check if an upload part error is transient, a network error or S3 throttling or server error, rather than e.g. access being denied
+ */
+fn upload_part_error_is_transient(error: &UploadPartError) -> bool
+{
+    match *error
+    {
+        UploadPartError::HttpDispatch(_) => true,
+        UploadPartError::Unknown(ref body) => error_body_is_transient(body),
+        _ => false
+    }
+}
+
+/**
+ * Wait before retrying an upload request, doubling the delay after each attempt
+ */
+fn retry_backoff(attempt: u32)
+{
+    let delay = RETRY_BASE_DELAY.saturating_mul(1 << (attempt - 1).min(16)).min(RETRY_MAX_DELAY);
+
+    thread::sleep(Duration::from_millis(delay));
+}
+
+/**
+ * Check if a local file is a checksums file, e.g. one left behind by an interrupted run
+ */
+fn local_file_is_checksums(file: &LocalFile, checksums_name: &String) -> bool
+{
+    return file.path.file_name().map_or(false, |file_name| file_name == checksums_name.as_str());
+}
+
+/**
+ * Check if a checksums file name can be used both as a file in the local path and as a key under the prefix
+ */
+pub fn checksums_name_is_valid(checksums_name: &str) -> bool
+{
+    return !checksums_name.is_empty() && checksums_name != "." && checksums_name != ".." && !checksums_name.contains("/") && !checksums_name.contains("\\");
+}
+
+/**
+ * Get the size in bytes of a local file
+ */
+pub fn local_file_get_size(file: &LocalFile) -> io::Result<u64>
+{
+    return fs::metadata(&file.path).map(|metadata| metadata.len());
+}
+
+/**
+ * Get the modification time of a file from its metadata in nanoseconds since the Unix epoch
+ */
+pub fn metadata_get_mtime(metadata: &fs::Metadata) -> Option<u64>
+{
+    let modified = metadata.modified().ok()?;
+    let since_epoch = modified.duration_since(UNIX_EPOCH).ok()?;
+
+    return Some(since_epoch.as_secs() * 1000000000 + since_epoch.subsec_nanos() as u64);
+}
+
+/**
+ * Get the modification time of a local file in nanoseconds since the Unix epoch, the one captured when the file was found or else the current one
+ */
+fn local_file_get_mtime(file: &LocalFile) -> Option<u64>
+{
+    if file.mtime.is_some()
+    {
+        return file.mtime;
+    }
+
+    return fs::metadata(&file.path).ok().and_then(|metadata| metadata_get_mtime(&metadata));
+}
+
+/**
+ * Check if a local file is compressed before it is uploaded
+ */
+fn local_file_is_gzipped(file: &LocalFile, options: &UploadOptions) -> bool
+{
+    return options.gzip && local_file_get_size(file).map_or(false, |size| size <= options.multipart_threshold) && mime_type_is_compressible(&local_file_get_mime(file, &options.mime_overrides, options.sniff_mime));
+}
+
+/**
+ * Struct for the memory budget of upload bodies held in memory at once
+ */
+pub struct MemoryBudget
+{
+    pub limit: u64,
+    pub in_use: Mutex<u64>,
+    pub released: Condvar
+}
+
+/**
+ * Reserve bytes from the memory budget, blocking until enough of the budget is free
+ */
+fn memory_budget_acquire(budget: &MemoryBudget, bytes: u64) -> u64
+{
+    // A body larger than the whole budget is still allowed once nothing else is reserved
+    let bytes = std::cmp::min(bytes, budget.limit);
+    let mut in_use = budget.in_use.lock().unwrap();
+
+    while in_use.saturating_add(bytes) > budget.limit
+    {
+        in_use = budget.released.wait(in_use).unwrap();
+    }
+
+    *in_use += bytes;
+
+    return bytes;
+}
+
+/**
+ * Release bytes previously reserved from the memory budget
+ */
+fn memory_budget_release(budget: &MemoryBudget, bytes: u64)
+{
+    let mut in_use = budget.in_use.lock().unwrap();
+    *in_use -= bytes;
+
+    budget.released.notify_all();
+}
+
+/**
+ * Get a number of bytes from a size parameter value, e.g. 1048576, 512KB, 5MB or 1GB
+ */
+pub fn parse_size(value: &str) -> Option<u64>
+{
+    let value = value.trim().to_uppercase();
+    let value = value.trim_end_matches('B');
+
+    let (number, multiplier) = match value.chars().last()
+    {
+        Some('K') => (&value[..value.len() - 1], 1024),
+        Some('M') => (&value[..value.len() - 1], 1024 * 1024),
+        Some('G') => (&value[..value.len() - 1], 1024 * 1024 * 1024),
+        _         => (value, 1)
+    };
+
+    match number.trim().parse::<u64>()
+    {
+        Ok(number) => number.checked_mul(multiplier),
+        Err(_)     => None
+    }
+}
+
+/**
+ * Get a duration from a duration parameter value in seconds, minutes, hours or days, e.g. 90s, 30m, 1h or 7d
+ */
+pub fn parse_duration(value: &str) -> Option<Duration>
+{
+    let value = value.trim().to_lowercase();
+
+    let (number, multiplier) = match value.chars().last()
+    {
+        Some('s') => (&value[..value.len() - 1], 1),
+        Some('m') => (&value[..value.len() - 1], 60),
+        Some('h') => (&value[..value.len() - 1], 60 * 60),
+        Some('d') => (&value[..value.len() - 1], 24 * 60 * 60),
+        _         => (&value[..], 1)
+    };
+
+    match number.trim().parse::<u64>()
+    {
+        Ok(number) => number.checked_mul(multiplier).map(Duration::from_secs),
+        Err(_)     => None
+    }
+}
+
+/**
+ * Set a custom header on a put object request using its matching field, returns false when the header can't be sent
+ */
+pub fn put_object_request_set_header(request: &mut PutObjectRequest, name: &str, value: &str) -> bool
+{
+    let name = name.trim().to_lowercase();
+    let value = String::from(value.trim());
+
+    if name.starts_with("x-amz-meta-") && name.len() > "x-amz-meta-".len()
+    {
+        request.metadata.get_or_insert_with(HashMap::new).insert(name["x-amz-meta-".len()..].to_owned(), value);
+        return true;
+    }
+
+    match name.as_ref()
+    {
+        "cache-control"                               => request.cache_control = Some(value),
+        "content-disposition"                         => request.content_disposition = Some(value),
+        "content-encoding"                            => request.content_encoding = Some(value),
+        "content-language"                            => request.content_language = Some(value),
+        "content-type"                                => request.content_type = Some(value),
+        "expires"                                     => request.expires = Some(value),
+        "x-amz-acl"                                   => request.acl = Some(value),
+        "x-amz-grant-full-control"                    => request.grant_full_control = Some(value),
+        "x-amz-grant-read"                            => request.grant_read = Some(value),
+        "x-amz-grant-read-acp"                        => request.grant_read_acp = Some(value),
+        "x-amz-grant-write-acp"                       => request.grant_write_acp = Some(value),
+        "x-amz-server-side-encryption"                => request.server_side_encryption = Some(value),
+        "x-amz-server-side-encryption-aws-kms-key-id" => request.ssekms_key_id = Some(value),
+        "x-amz-storage-class"                         => request.storage_class = Some(value),
+        "x-amz-tagging"                               => request.tagging = Some(value),
+        "x-amz-website-redirect-location"             => request.website_redirect_location = Some(value),
+        _                                             => return false
+    }
+
+    return true;
+}
+
+/**
+ * Enum for the source of the AWS credentials
+ */
+enum CredentialsProvider
+{
+    Profile(ProfileProvider),
+    Environment(EnvironmentProvider),
+    AssumedRole(Arc<AssumedRoleProvider>)
+}
+
+impl ProvideAwsCredentials for CredentialsProvider
+{
+    fn credentials(&self) -> Result<AwsCredentials, CredentialsError>
+    {
+        match *self
+        {
+            CredentialsProvider::Profile(ref provider)     => provider.credentials(),
+            CredentialsProvider::Environment(ref provider) => provider.credentials(),
+            CredentialsProvider::AssumedRole(ref provider) => provider.credentials()
+        }
+    }
+}
+
+impl Clone for CredentialsProvider
+{
+    fn clone(&self) -> CredentialsProvider
+    {
+        match *self
+        {
+            CredentialsProvider::Profile(ref provider)     => CredentialsProvider::Profile(provider.clone()),
+            CredentialsProvider::Environment(_)            => CredentialsProvider::Environment(EnvironmentProvider),
+            CredentialsProvider::AssumedRole(ref provider) => CredentialsProvider::AssumedRole(provider.clone())
+        }
+    }
+}
+
+/**
+ * Struct for the temporary credentials of a role assumed through STS with the source credentials, kept until they are close to expiring
+ */
+struct AssumedRoleProvider
+{
+    role_arn: String,
+    source_provider: Box<CredentialsProvider>,
+    region: Region,
+    http_options: HttpOptions,
+    credentials: Mutex<Option<(AwsCredentials, Instant)>>
+}
+
+impl ProvideAwsCredentials for AssumedRoleProvider
+{
+    fn credentials(&self) -> Result<AwsCredentials, CredentialsError>
+    {
+        // The lock is held while STS is called, so concurrent uploads wait for the same credentials instead of each assuming the role
+        let mut credentials = self.credentials.lock().unwrap();
+
+        if let Some((ref role_credentials, ref requested_at)) = *credentials
+        {
+            if requested_at.elapsed() < Duration::from_secs(ASSUMED_ROLE_DURATION - ASSUMED_ROLE_REFRESH_MARGIN)
+            {
+                return Ok(role_credentials.clone());
+            }
+        }
+
+        let http_client = create_http_client(&self.http_options).map_err(CredentialsError::new)?;
+        let sts_client = StsClient::new(http_client, (*self.source_provider).clone(), self.region);
+
+        let request = AssumeRoleRequest
+        {
+            role_arn: self.role_arn.to_owned(),
+            role_session_name: String::from(ASSUMED_ROLE_SESSION_NAME),
+            duration_seconds: Some(ASSUMED_ROLE_DURATION as i32),
+            ..Default::default()
+        };
+
+        let requested_at = Instant::now();
+        let role_credentials = match sts_client.assume_role(&request)
+        {
+            Ok(response) => response.credentials.ok_or(CredentialsError::new("STS returned no credentials"))?,
+            Err(error) => return Err(CredentialsError::new(format!("could not assume the role \"{}\": {}", self.role_arn, error)))
+        };
+        let role_credentials = AwsCredentials::new_for_credentials(role_credentials)?;
+
+        *credentials = Some((role_credentials.clone(), requested_at));
+
+        return Ok(role_credentials);
+    }
+}
+
+/**
+ * Check if a header name only has the token characters HTTP allows in one
+ */
+pub fn header_name_is_valid(name: &str) -> bool
+{
+    return !name.is_empty() && name.chars().all(|character| character.is_ascii_alphanumeric() || "!#$%&'*+-.^_`|~".contains(character));
+}
+
+/**
+ * Check if a header value only has visible ASCII characters, spaces and tabs
+ */
+pub fn header_value_is_valid(value: &str) -> bool
+{
+    return value.chars().all(|character| character == ' ' || character == '\t' || (character.is_ascii() && !character.is_ascii_control()));
+}
+
+/**
+ * Check if the AWS credentials environment variables are set
+ */
+pub fn environment_credentials_exist() -> bool
+{
+    let access_key_id = env::var("AWS_ACCESS_KEY_ID").unwrap_or(String::from(""));
+    let secret_access_key = env::var("AWS_SECRET_ACCESS_KEY").unwrap_or(String::from(""));
+
+    return !access_key_id.is_empty() && !secret_access_key.is_empty();
+}
+
+/**
+ * Get the path of the AWS config file, from the AWS_CONFIG_FILE environment variable or else ~/.aws/config
+ */
+pub fn get_aws_config_path() -> Option<PathBuf>
+{
+    if let Ok(config_path) = env::var("AWS_CONFIG_FILE")
+    {
+        if !config_path.trim().is_empty()
+        {
+            return Some(PathBuf::from(config_path.trim()));
+        }
+    }
+
+    // Windows has no HOME, the AWS CLI uses the user profile directory there
+    let home = env::var("HOME").or_else(|_| env::var("USERPROFILE")).ok()?;
+
+    if home.trim().is_empty()
+    {
+        return None;
+    }
+
+    return Some(Path::new(home.trim()).join(".aws").join("config"));
+}
+
+/**
+ * Get the region of a profile from an AWS config file, where it is in a [profile NAME] section, or [default] for the default profile
+ */
+pub fn get_profile_config_region(config_path: &Path, profile: &String) -> Option<String>
+{
+    let mut contents = String::new();
+    File::open(config_path).and_then(|mut file| file.read_to_string(&mut contents)).ok()?;
+
+    let mut in_profile = false;
+
+    for line in contents.lines()
+    {
+        let line = line.trim();
+
+        if line.starts_with("[") && line.ends_with("]")
+        {
+            let section: Vec<&str> = line[1..line.len() - 1].split_whitespace().collect();
+            in_profile = section == ["profile", profile.as_str()] || (profile == "default" && section == ["default"]);
+            continue;
+        }
+
+        if in_profile
+        {
+            let mut setting = line.splitn(2, "=");
+
+            if let (Some(name), Some(value)) = (setting.next(), setting.next())
+            {
+                if name.trim() == "region" && !value.trim().is_empty()
+                {
+                    return Some(value.trim().to_owned());
+                }
+            }
+        }
+    }
+
+    return None;
+}
+
+/**
+ * Check if stdin is an interactive terminal
+ */
+pub fn stdin_is_terminal() -> bool
+{
+    return unsafe { libc::isatty(0) } != 0;
+}
+
+/**
+ * Get a text colored for stderr, term-painter only colors stdout so the color is only added when stderr is a terminal
+ */
+pub fn stderr_paint<T: fmt::Display>(color: term_painter::Color, text: T) -> String
+{
+    if unsafe { libc::isatty(2) } == 0
+    {
+        return format!("{}", text);
+    }
+
+    let code = match color
+    {
+        Red => 31,
+        Green => 32,
+        Yellow => 33,
+        _ => 39
+    };
+
+    return format!("\x1b[{}m{}\x1b[0m", code, text);
+}
+
+/**
+ * Print an error line in red on stderr
+ */
+pub fn print_error(message: &str)
+{
+    eprintln!("{}", stderr_paint(Red, message));
+}
+
+/**
+ * Get the credentials provider, using the credentials file unless it is missing and the AWS environment variables are set
+ */
+fn get_credentials_provider(credentials_path: &String, profile: &String) -> CredentialsProvider
+{
+    if !Path::new(credentials_path).exists() && environment_credentials_exist()
+    {
+        return CredentialsProvider::Environment(EnvironmentProvider);
+    }
+
+    return CredentialsProvider::Profile(ProfileProvider::with_configuration(Path::new(credentials_path), profile.as_str()));
+}
+
+/**
+ * Get the credentials provider of the requests, assuming the role with the source credentials when one is set
+ */
+fn get_role_credentials_provider(credentials_path: &String, profile: &String, assume_role: &Option<String>, region: Region, http_options: &HttpOptions) -> CredentialsProvider
+{
+    let source_provider = get_credentials_provider(credentials_path, profile);
+
+    match *assume_role
+    {
+        Some(ref role_arn) =>
+        {
+            return CredentialsProvider::AssumedRole(Arc::new(AssumedRoleProvider
+            {
+                role_arn: role_arn.to_owned(),
+                source_provider: Box::new(source_provider),
+                region: region,
+                http_options: http_options.clone(),
+                credentials: Mutex::new(None)
+            }));
+        }
+        None =>
+        {
+            return source_provider;
+        }
+    }
+}
+
+/**
+ * Struct for a custom S3 compatible endpoint, e.g. MinIO or DigitalOcean Spaces
+ */
+#[derive(Clone)]
+pub struct Endpoint
+{
+    pub scheme: String,
+    pub host: String
+}
+
+/**
+ * Get a value percent-encoded for a query string, keeping only the unreserved characters as they are
+ */
+fn url_encode(value: &str) -> String
+{
+    let mut encoded = String::new();
+
+    for byte in value.bytes()
+    {
+        if byte.is_ascii_alphanumeric() || b"-_.~".contains(&byte)
+        {
+            encoded.push(byte as char);
+        }
+        else
+        {
+            encoded.push_str(&format!("%{:02X}", byte));
+        }
+    }
+
+    return encoded;
+}
+
+/**
+ * Get the url-encoded tagging of an object from a tag parameter value, e.g. env:prod,team:web becomes env=prod&team=web
+ */
+pub fn parse_tags(value: &str) -> Result<String, String>
+{
+    let mut tags: Vec<(String, String)> = Vec::new();
+
+    for tag in value.split(",").filter(|tag| !tag.trim().is_empty())
+    {
+        let mut tag_split = tag.splitn(2, ":");
+        let key = tag_split.next().unwrap_or("").trim();
+        let value = tag_split.next().unwrap_or("").trim();
+
+        // S3 allows letters, digits, spaces and +-=._:/@ in tags, with keys of up to 128 characters and values of up to 256
+        let allowed = |character: char| character.is_alphanumeric() || " +-=._:/@".contains(character);
+
+        if key.is_empty() || key.chars().count() > 128 || value.chars().count() > 256 || !key.chars().all(&allowed) || !value.chars().all(&allowed)
+        {
+            return Err(format!("must be in the form key:value with a key of up to 128 and a value of up to 256 letters, digits, spaces and +-=._:/@, got \"{}\"", tag));
+        }
+
+        if tags.iter().any(|&(ref existing_key, _)| existing_key == key)
+        {
+            return Err(format!("sets the tag \"{}\" more than once", key));
+        }
+
+        tags.push((String::from(key), String::from(value)));
+    }
+
+    if tags.len() > MAX_TAGS
+    {
+        return Err(format!("can set at most {} tags, got {}", MAX_TAGS, tags.len()));
+    }
+
+    let tagging: Vec<String> = tags.iter().map(|&(ref key, ref value)| format!("{}={}", url_encode(key), url_encode(value))).collect();
+
+    return Ok(tagging.join("&"));
+}
+
+/**
+ * Struct for the connection options of the HTTP clients, the proxy requests are sent through, the CA certificates trusted on top of the system ones and the timeout of a stalled connection
+ */
+#[derive(Clone)]
+pub struct HttpOptions
+{
+    pub proxy: Option<(String, u16)>,
+    pub ca_certificates: Vec<Vec<u8>>,
+    pub timeout: Duration
+}
+
+/**
+ * Get the host and port of a proxy from a proxy environment variable value, e.g. http://proxy.corp:3128
+ */
+pub fn parse_proxy(value: &str) -> Option<(String, u16)>
+{
+    let value = value.trim().trim_end_matches('/');
+    let address = if value.starts_with("http://") { &value["http://".len()..] } else { value };
+
+    // Proxies asking for credentials and ones reached over TLS aren't supported
+    if address.is_empty() || address.contains("://") || address.contains("@") || address.contains("/")
+    {
+        return None;
+    }
+
+    match address.rfind(":")
+    {
+        Some(index) => address[index + 1..].parse::<u16>().ok().map(|port| (String::from(&address[..index]), port)),
+        None => Some((String::from(address), 80))
+    }
+}
+
+/**
+ * Get the proxy set by the HTTPS_PROXY or HTTP_PROXY environment variables (or their lowercase forms), unless NO_PROXY lists the endpoint host
+ */
+pub fn get_environment_proxy(endpoint: &Option<Endpoint>) -> Result<Option<(String, u16)>, String>
+{
+    let variable = ["HTTPS_PROXY", "https_proxy", "HTTP_PROXY", "http_proxy"].iter()
+        .filter_map(|name| env::var(name).ok().filter(|value| !value.trim().is_empty()).map(|value| (*name, value)))
+        .next();
+
+    let (name, value) = match variable
+    {
+        Some(variable) => variable,
+        None => return Ok(None)
+    };
+
+    // Only the custom endpoint can be matched against NO_PROXY, as the AWS host depends on the bucket and region
+    if let Some(ref endpoint) = *endpoint
+    {
+        let host = endpoint.host.split(":").next().unwrap_or("");
+        let no_proxy = env::var("NO_PROXY").or(env::var("no_proxy")).unwrap_or(String::new());
+
+        for pattern in no_proxy.split(",").map(|pattern| pattern.trim().trim_start_matches('.')).filter(|pattern| !pattern.is_empty())
+        {
+            if pattern == "*" || host == pattern || host.ends_with(&format!(".{}", pattern))
+            {
+                return Ok(None);
+            }
+        }
+    }
+
+    return parse_proxy(&value).map(Some).ok_or(format!("{} must be an http proxy address without credentials, e.g. http://proxy.corp:3128, got \"{}\"", name, value));
+}
+
+/**
+ * Get the DER contents of the certificates in a PEM CA bundle, e.g. the bundle of a corporate proxy
+ */
+pub fn parse_ca_bundle(contents: &str) -> Result<Vec<Vec<u8>>, String>
+{
+    let mut certificates: Vec<Vec<u8>> = Vec::new();
+    let mut certificate: Option<String> = None;
+
+    for line in contents.lines().map(|line| line.trim())
+    {
+        if line == "-----BEGIN CERTIFICATE-----"
+        {
+            certificate = Some(String::new());
+        }
+        else if line == "-----END CERTIFICATE-----"
+        {
+            let encoded = certificate.take().ok_or(String::from("a certificate ends before it begins"))?;
+            let der = base64::decode(&encoded).map_err(|error| format!("a certificate is not valid base64: {}", error))?;
+            Certificate::from_der(&der).map_err(|error| format!("a certificate could not be read: {}", error))?;
+
+            certificates.push(der);
+        }
+        else if let Some(ref mut encoded) = certificate
+        {
+            encoded.push_str(line);
+        }
+    }
+
+    if certificates.is_empty()
+    {
+        return Err(String::from("no PEM certificates were found"));
+    }
+
+    return Ok(certificates);
+}
+
+/**
+ * Create the HTTP client every AWS and endpoint request is sent with, through the proxy and trusting the CA certificates of the options
+ */
+pub fn create_http_client(http_options: &HttpOptions) -> Result<hyper::Client, String>
+{
+    let mut tls_builder = TlsConnector::builder().map_err(|error| error.to_string())?;
+
+    for der in &http_options.ca_certificates
+    {
+        let certificate = Certificate::from_der(der).map_err(|error| error.to_string())?;
+        tls_builder.add_root_certificate(certificate).map_err(|error| error.to_string())?;
+    }
+
+    let ssl = NativeTlsClient::from(tls_builder.build().map_err(|error| error.to_string())?);
+
+    let mut client = match http_options.proxy
+    {
+        Some((ref host, port)) => hyper::Client::with_proxy_config(ProxyConfig::new("http", host.to_owned(), port, HttpConnector, ssl)),
+        None => hyper::Client::with_connector(HttpsConnector::new(ssl))
+    };
+
+    // Redirects aren't followed, as the S3 client handles the errors S3 redirects with, the same as rusoto's default client
+    client.set_redirect_policy(RedirectPolicy::FollowNone);
+
+    // A request whose connection sends or receives nothing for the timeout fails with a network error, which uploads retry
+    client.set_read_timeout(Some(http_options.timeout));
+    client.set_write_timeout(Some(http_options.timeout));
+
+    return Ok(client);
+}
+
+/**
+ * Get an endpoint from an endpoint parameter value, e.g. https://minio.local:9000
+ */
+pub fn parse_endpoint(value: &str) -> Option<Endpoint>
+{
+    let mut endpoint_split = value.trim().splitn(2, "://");
+
+    match (endpoint_split.next(), endpoint_split.next())
+    {
+        (Some(scheme), Some(host)) if (scheme == "http" || scheme == "https") && !host.trim_end_matches('/').is_empty() && !host.trim_end_matches('/').contains("/") =>
+        {
+            Some(Endpoint { scheme: String::from(scheme), host: String::from(host.trim_end_matches('/')) })
+        }
+        _ => None
+    }
+}
+
+/**
+ * Struct for the bandwidth limit shared by every upload, the time the next chunk may be sent is pushed back by each chunk sent
+ */
+pub struct BandwidthLimit
+{
+    pub bytes_per_second: u64,
+    pub next_send: Mutex<Instant>
+}
+
+/**
+ * Wait until a chunk of bytes can be sent without going over the bandwidth limit
+ */
+fn bandwidth_limit_wait(limit: &BandwidthLimit, bytes: usize)
+{
+    let send_at =
+    {
+        let mut next_send = limit.next_send.lock().unwrap();
+        let now = Instant::now();
+        let send_at = if *next_send > now { *next_send } else { now };
+
+        *next_send = send_at + Duration::from_micros(bytes as u64 * 1000000 / limit.bytes_per_second);
+
+        send_at
+    };
+
+    let now = Instant::now();
+
+    if send_at > now
+    {
+        thread::sleep(send_at - now);
+    }
+}
+
+/**
+ * Struct for a request body read in chunks no faster than the bandwidth limit
+ */
+struct ThrottledBody<'a>
+{
+    body: &'a [u8],
+    limit: &'a BandwidthLimit
+}
+
+impl<'a> Read for ThrottledBody<'a>
+{
+    fn read(&mut self, buffer: &mut [u8]) -> io::Result<usize>
+    {
+        let bytes = std::cmp::min(std::cmp::min(buffer.len(), BANDWIDTH_CHUNK_SIZE), self.body.len());
+
+        if bytes > 0
+        {
+            bandwidth_limit_wait(self.limit, bytes);
+        }
+
+        buffer[..bytes].copy_from_slice(&self.body[..bytes]);
+        self.body = &self.body[bytes..];
+
+        return Ok(bytes);
+    }
+}
+
+/**
+ * Struct for the dispatcher of bucket requests, sending them to the custom endpoint instead of AWS when one is set, limiting their bandwidth if a limit is set and adding the headers the S3 client doesn't send for the objects being uploaded
+ */
+struct BucketDispatcher
+{
+    client: hyper::Client,
+    endpoint: Option<Endpoint>,
+    credentials_provider: CredentialsProvider,
+    bandwidth_limit: Option<Arc<BandwidthLimit>>,
+    object_headers: Arc<Mutex<HashMap<String, HashMap<String, String>>>>
+}
+
+impl DispatchSignedRequest for BucketDispatcher
+{
+    fn dispatch(&self, request: &SignedRequest) -> Result<HttpResponse, HttpDispatchError>
+    {
+        // Only the PutObject and CreateMultipartUpload requests for an object carry its headers, not its upload parts
+        let uploads_object = (request.method == "PUT" && !request.params.contains_key("partNumber")) || (request.method == "POST" && request.params.contains_key("uploads"));
+        let headers = if uploads_object { self.object_headers.lock().unwrap().get(&request.path).cloned() } else { None };
+
+        match (&self.endpoint, &headers, &self.bandwidth_limit)
+        {
+            (&Some(ref endpoint), _, _) => resigned_dispatch(&self.client, &endpoint.scheme, &endpoint.host, &self.credentials_provider, request, &headers, &self.bandwidth_limit),
+            (&None, &Some(_), _) => resigned_dispatch(&self.client, "https", &request.hostname(), &self.credentials_provider, request, &headers, &self.bandwidth_limit),
+            (&None, &None, &Some(_)) => host_dispatch(&self.client, "https", &request.hostname(), request, &self.bandwidth_limit),
+            (&None, &None, &None) => self.client.dispatch(request)
+        }
+    }
+}
+
+/**
+ * Send a request to a host with the object headers added, signing it again as the signature covers the headers and the AWS hostname it was built for
+ */
+fn resigned_dispatch(client: &hyper::Client, scheme: &str, host: &str, credentials_provider: &CredentialsProvider, request: &SignedRequest, headers: &Option<HashMap<String, String>>, bandwidth_limit: &Option<Arc<BandwidthLimit>>) -> Result<HttpResponse, HttpDispatchError>
+{
+    let mut endpoint_request = SignedRequest::new(&request.method, &request.service, request.region, &request.path);
+    endpoint_request.headers = request.headers.clone();
+    endpoint_request.params = request.params.clone();
+    endpoint_request.payload = request.payload.clone();
+    endpoint_request.set_hostname(Some(host.to_owned()));
+
+    // The headers set by the first signature are dropped, a content-length left in place would be listed as an empty signed header
+    endpoint_request.remove_header("authorization");
+    endpoint_request.remove_header("content-length");
+
+    // The S3 client in use doesn't send the metadata of a request or the tagging of a multipart upload, so their headers are added here
+    if let Some(ref headers) = *headers
+    {
+        for (name, value) in headers
+        {
+            endpoint_request.add_header(name, value);
+        }
+    }
+
+    match credentials_provider.credentials()
+    {
+        Ok(credentials) => endpoint_request.sign(&credentials),
+        Err(error) => return Err(HttpDispatchError::from(io::Error::new(io::ErrorKind::Other, error.to_string())))
+    }
+
+    return host_dispatch(client, scheme, host, &endpoint_request, bandwidth_limit);
+}
+
+/**
+ * Send a signed request to a host, feeding its body no faster than the bandwidth limit when one is set
+ */
+fn host_dispatch(client: &hyper::Client, scheme: &str, host: &str, endpoint_request: &SignedRequest, bandwidth_limit: &Option<Arc<BandwidthLimit>>) -> Result<HttpResponse, HttpDispatchError>
+{
+    let method = match endpoint_request.method()
+    {
+        "POST"   => Method::Post,
+        "PUT"    => Method::Put,
+        "DELETE" => Method::Delete,
+        "GET"    => Method::Get,
+        "HEAD"   => Method::Head,
+        method   => return Err(HttpDispatchError::from(io::Error::new(io::ErrorKind::InvalidInput, format!("Unsupported HTTP verb {}", method))))
+    };
+
+    let mut headers = Headers::new();
+    for (name, values) in endpoint_request.headers()
+    {
+        headers.set_raw(name.to_owned(), values.to_owned());
+    }
+
+    // Buckets are always addressed by path on custom endpoints, e.g. https://minio.local:9000/bucket/key, as most S3 compatible servers don't support bucket subdomains
+    let mut uri = format!("{}://{}{}", scheme, host, endpoint_request.canonical_path());
+    if !endpoint_request.canonical_query_string().is_empty()
+    {
+        uri = format!("{}?{}", uri, endpoint_request.canonical_query_string());
+    }
+
+    let body: &[u8] = match endpoint_request.payload
+    {
+        Some(ref payload) => payload.as_slice(),
+        None => b""
+    };
+
+    let mut response = match *bandwidth_limit
+    {
+        Some(ref limit) =>
+        {
+            let mut throttled_body = ThrottledBody { body: body, limit: limit };
+            client.request(method, &uri).headers(headers).body(hyper::client::Body::SizedBody(&mut throttled_body, body.len() as u64)).send()?
+        }
+        None => client.request(method, &uri).headers(headers).body(body).send()?
+    };
+    let mut response_body: Vec<u8> = Vec::new();
+    response.read_to_end(&mut response_body)?;
+
+    let mut response_headers: HashMap<String, String> = HashMap::new();
+    for header in response.headers.iter()
+    {
+        response_headers.insert(header.name().to_string(), header.value_string());
+    }
+
+    return Ok(HttpResponse { status: response.status.clone(), body: response_body, headers: response_headers });
+}
+
+/**
+ * Struct for the S3 client shared by all bucket operations, with the headers of the objects being uploaded that its dispatcher adds to their requests
+ */
+pub struct BucketClient
+{
+    s3_client: S3Client<CredentialsProvider, BucketDispatcher>,
+    object_headers: Arc<Mutex<HashMap<String, HashMap<String, String>>>>
+}
+
+/**
+ * Trait for the bucket operations of a run, so they can be sent to another store than the S3 client
+ */
+pub trait ObjectStore: Send + Sync
+{
+    fn head_bucket(&self, request: &HeadBucketRequest) -> Result<(), HeadBucketError>;
+    fn list_objects_v2(&self, request: &ListObjectsV2Request) -> Result<ListObjectsV2Output, ListObjectsV2Error>;
+    fn get_object(&self, request: &GetObjectRequest) -> Result<GetObjectOutput, GetObjectError>;
+    fn put_object(&self, request: &PutObjectRequest) -> Result<PutObjectOutput, PutObjectError>;
+    fn create_multipart_upload(&self, request: &CreateMultipartUploadRequest) -> Result<CreateMultipartUploadOutput, CreateMultipartUploadError>;
+    fn upload_part(&self, request: &UploadPartRequest) -> Result<UploadPartOutput, UploadPartError>;
+    fn complete_multipart_upload(&self, request: &CompleteMultipartUploadRequest) -> Result<CompleteMultipartUploadOutput, CompleteMultipartUploadError>;
+    fn abort_multipart_upload(&self, request: &AbortMultipartUploadRequest) -> Result<AbortMultipartUploadOutput, AbortMultipartUploadError>;
+    fn delete_objects(&self, request: &DeleteObjectsRequest) -> Result<DeleteObjectsOutput, DeleteObjectsError>;
+
+    /**
+     * Set the headers the S3 client doesn't send with the requests uploading an object, such as its metadata, or clear them if there are none
+     */
+    fn set_object_headers(&self, bucket_name: &String, key: &String, headers: &Option<HashMap<String, String>>);
+}
+
+impl ObjectStore for BucketClient
+{
+    fn head_bucket(&self, request: &HeadBucketRequest) -> Result<(), HeadBucketError>
+    {
+        return self.s3_client.head_bucket(request);
+    }
+
+    fn list_objects_v2(&self, request: &ListObjectsV2Request) -> Result<ListObjectsV2Output, ListObjectsV2Error>
+    {
+        return self.s3_client.list_objects_v2(request);
+    }
+
+    fn get_object(&self, request: &GetObjectRequest) -> Result<GetObjectOutput, GetObjectError>
+    {
+        return self.s3_client.get_object(request);
+    }
+
+    fn put_object(&self, request: &PutObjectRequest) -> Result<PutObjectOutput, PutObjectError>
+    {
+        return self.s3_client.put_object(request);
+    }
+
+    fn create_multipart_upload(&self, request: &CreateMultipartUploadRequest) -> Result<CreateMultipartUploadOutput, CreateMultipartUploadError>
+    {
+        return self.s3_client.create_multipart_upload(request);
+    }
+
+    fn upload_part(&self, request: &UploadPartRequest) -> Result<UploadPartOutput, UploadPartError>
+    {
+        return self.s3_client.upload_part(request);
+    }
+
+    fn complete_multipart_upload(&self, request: &CompleteMultipartUploadRequest) -> Result<CompleteMultipartUploadOutput, CompleteMultipartUploadError>
+    {
+        return self.s3_client.complete_multipart_upload(request);
+    }
+
+    fn abort_multipart_upload(&self, request: &AbortMultipartUploadRequest) -> Result<AbortMultipartUploadOutput, AbortMultipartUploadError>
+    {
+        return self.s3_client.abort_multipart_upload(request);
+    }
+
+    fn delete_objects(&self, request: &DeleteObjectsRequest) -> Result<DeleteObjectsOutput, DeleteObjectsError>
+    {
+        return self.s3_client.delete_objects(request);
+    }
+
+    fn set_object_headers(&self, bucket_name: &String, key: &String, headers: &Option<HashMap<String, String>>)
+    {
+        let path = format!("/{}/{}", bucket_name, key);
+        let mut object_headers = self.object_headers.lock().unwrap();
+
+        match *headers
+        {
+            Some(ref headers) if !headers.is_empty() =>
+            {
+                object_headers.insert(path, headers.to_owned());
+            }
+            _ =>
+            {
+                object_headers.remove(&path);
+            }
+        }
+    }
+}
+
+/**
+ * Create the S3 client for a region, using the assumed role, custom endpoint, bandwidth limit, proxy and CA certificates when they are set
+ */
+pub fn create_bucket_client(region: Region, credentials_path: &String, profile: &String, assume_role: &Option<String>, endpoint: &Option<Endpoint>, bandwidth_limit: &Option<Arc<BandwidthLimit>>, http_options: &HttpOptions) -> BucketClient
+{
+    let object_headers = Arc::new(Mutex::new(HashMap::new()));
+
+    // The dispatcher and the S3 client share the assumed role credentials, so the role is only assumed once
+    let credentials_provider = get_role_credentials_provider(credentials_path, profile, assume_role, region, http_options);
+
+    let dispatcher = BucketDispatcher
+    {
+        client: create_http_client(http_options).expect("Could not create TLS client"),
+        endpoint: endpoint.clone(),
+        credentials_provider: credentials_provider.clone(),
+        bandwidth_limit: bandwidth_limit.clone(),
+        object_headers: object_headers.clone()
+    };
+
+    return BucketClient { s3_client: S3Client::new(dispatcher, credentials_provider, region), object_headers: object_headers };
+}
+
+/**
+ * Get the CloudFront invalidation paths for bucket keys, or a single wildcard path for the prefix when there are too many keys to invalidate one by one
+ */
+pub fn cloudfront_invalidation_paths(keys: &Vec<String>, prefix: &String) -> Vec<String>
+{
+    // Each path segment is percent-encoded, so a * in a key is invalidated as itself rather than as a wildcard
+    let encode_path = |key: &String| key.split("/").map(url_encode).collect::<Vec<String>>().join("/");
+
+    if keys.len() > CLOUDFRONT_MAX_PATHS
+    {
+        return vec![format!("/{}*", encode_path(prefix))];
+    }
+
+    return keys.iter().map(|key| format!("/{}", encode_path(key))).collect();
+}
+
+/**
+ * Get the text of the first element with a name in an XML document, e.g. the Id of an invalidation or the Message of an error
+ */
+fn xml_element_text(document: &str, name: &str) -> Option<String>
+{
+    let start_tag = format!("<{}>", name);
+    let end_tag = format!("</{}>", name);
+
+    let start = document.find(&start_tag)? + start_tag.len();
+    let end = document[start..].find(&end_tag)? + start;
+
+    return Some(String::from(&document[start..end]));
+}
+
+/**
+ * Create a CloudFront invalidation of paths in a distribution, returning the ID of the invalidation
+ *
+ * The request is built and signed here, as the CloudFront client of this rusoto version serializes the paths of an invalidation with the wrong element names
+ */
+pub fn create_cloudfront_invalidation(distribution_id: &String, paths: &Vec<String>, credentials_path: &String, profile: &String, assume_role: &Option<String>, http_options: &HttpOptions) -> Result<String, String>
+{
+    let since_epoch = SystemTime::now().duration_since(UNIX_EPOCH).map_err(|error| error.to_string())?;
+    let items: Vec<String> = paths.iter().map(|path| format!("<Path>{}</Path>", path)).collect();
+
+    // The caller reference must be unique to each invalidation, the time of the request is used
+    let invalidation_batch = format!("<?xml version=\"1.0\" encoding=\"UTF-8\"?><InvalidationBatch xmlns=\"http://cloudfront.amazonaws.com/doc/2020-05-31/\"><Paths><Quantity>{}</Quantity><Items>{}</Items></Paths><CallerReference>s3-file-upload-{}{:09}</CallerReference></InvalidationBatch>",
+        paths.len(), items.join(""), since_epoch.as_secs(), since_epoch.subsec_nanos());
+
+    let mut request = SignedRequest::new("POST", "cloudfront", Region::UsEast1, &format!("/2020-05-31/distribution/{}/invalidation", distribution_id));
+    request.set_hostname(Some(String::from(CLOUDFRONT_HOST)));
+    request.add_header("content-type", "application/xml");
+    request.set_payload(Some(invalidation_batch.into_bytes()));
+
+    let credentials_provider = get_role_credentials_provider(credentials_path, profile, assume_role, Region::UsEast1, http_options);
+    request.sign(&credentials_provider.credentials().map_err(|error| error.to_string())?);
+
+    let client = create_http_client(http_options)?;
+    let response = host_dispatch(&client, "https", CLOUDFRONT_HOST, &request, &None).map_err(|error| error.to_string())?;
+    let body = String::from_utf8_lossy(&response.body);
+
+    if !response.status.is_success()
+    {
+        return Err(xml_element_text(&body, "Message").unwrap_or(response.status.to_string()));
+    }
+
+    return xml_element_text(&body, "Id").ok_or(String::from("CloudFront returned no invalidation ID"));
+}
+
+/**
+ * Get the part size for a multipart upload of a file, growing past the smallest part size when needed to stay under the part limit
+ */
+fn multipart_part_size(file_size: u64) -> u64
+{
+    let part_size = (file_size + MULTIPART_MAX_PARTS - 1) / MULTIPART_MAX_PARTS;
+
+    return std::cmp::max(part_size, MULTIPART_PART_SIZE);
+}
+
+/**
+ * Get the number of bytes of a local file held in memory while it is uploaded
+ */
+fn local_file_get_upload_size(file: &LocalFile, multipart_threshold: u64) -> u64
+{
+    // A file whose size can't be read fails when it is opened for the upload, so it reserves nothing
+    let file_size = local_file_get_size(file).unwrap_or(0);
+
+    if file_size > multipart_threshold
+    {
+        return std::cmp::min(file_size, multipart_part_size(file_size));
+    }
+
+    return file_size;
+}
+
+/**
+ * Upload the parts of a local file for a multipart upload
+ */
+fn local_file_upload_parts(client: &dyn ObjectStore, file: &LocalFile, request: &PutObjectRequest, upload_id: &String, max_retries: u32) -> Result<Vec<CompletedPart>, UploadError>
+{
+    let part_size = multipart_part_size(local_file_get_size(file)?);
+    let mut file_handle = File::open(&file.path)?;
+
+    let mut parts: Vec<CompletedPart> = Vec::new();
+    let mut part_number = 1;
+
+    loop
+    {
+        let mut body: Vec<u8> = Vec::new();
+
+        (&mut file_handle).take(part_size).read_to_end(&mut body)?;
+
+        if body.len() == 0
+        {
+            return Ok(parts);
+        }
+
+        let part_request = UploadPartRequest
+        {
+            bucket: request.bucket.to_owned(),
+            key: request.key.to_owned(),
+            content_length: Some(body.len() as i64),
+            content_md5: Some(get_content_md5("", &body)),
+            body: Some(body),
+            part_number: part_number,
+            upload_id: upload_id.to_owned(),
+            ..UploadPartRequest::default()
+        };
+
+        let mut attempt = 1;
+
+        loop
+        {
+            match client.upload_part(&part_request)
+            {
+                Ok(part_output) =>
+                {
+                    parts.push(CompletedPart { e_tag: part_output.e_tag, part_number: Some(part_number) });
+                    break;
+                }
+                Err(ref error) if attempt <= max_retries && upload_part_error_is_transient(error) =>
+                {
+                    retry_backoff(attempt);
+                    attempt += 1;
+                }
+                Err(error) =>
+                {
+                    return Err(UploadError::Request(format!("part {}: {}", part_number, error)));
+                }
+            }
+        }
+
+        part_number += 1;
+    }
+}
+
+/**
+ * Upload a local file to an s3 bucket location in parts, aborting the multipart upload if any part fails
+ */
+fn local_file_upload_multipart(client: &dyn ObjectStore, file: &LocalFile, request: &PutObjectRequest, max_retries: u32) -> Result<(), UploadError>
+{
+    let create_request = CreateMultipartUploadRequest
+    {
+        bucket: request.bucket.to_owned(),
+        key: request.key.to_owned(),
+        acl: request.acl.to_owned(),
+        cache_control: request.cache_control.to_owned(),
+        content_disposition: request.content_disposition.to_owned(),
+        content_encoding: request.content_encoding.to_owned(),
+        content_language: request.content_language.to_owned(),
+        content_type: request.content_type.to_owned(),
+        expires: request.expires.to_owned(),
+        grant_full_control: request.grant_full_control.to_owned(),
+        grant_read: request.grant_read.to_owned(),
+        grant_read_acp: request.grant_read_acp.to_owned(),
+        grant_write_acp: request.grant_write_acp.to_owned(),
+        metadata: request.metadata.to_owned(),
+        server_side_encryption: request.server_side_encryption.to_owned(),
+        ssekms_key_id: request.ssekms_key_id.to_owned(),
+        storage_class: request.storage_class.to_owned(),
+        website_redirect_location: request.website_redirect_location.to_owned(),
+        ..CreateMultipartUploadRequest::default()
+    };
+
+    let upload_id = match client.create_multipart_upload(&create_request)
+    {
+        Ok(create_output) =>
+        {
+            match create_output.upload_id
+            {
+                Some(upload_id) => upload_id,
+                None => return Err(UploadError::Request(String::from("no upload id was returned for the multipart upload")))
+            }
+        }
+        Err(error) => return Err(UploadError::Request(error.to_string()))
+    };
+
+    let error = match local_file_upload_parts(client, file, request, &upload_id, max_retries)
+    {
+        Ok(parts) =>
+        {
+            let complete_request = CompleteMultipartUploadRequest
+            {
+                bucket: request.bucket.to_owned(),
+                key: request.key.to_owned(),
+                upload_id: upload_id.to_owned(),
+                multipart_upload: Some(CompletedMultipartUpload { parts: Some(parts) }),
+                ..CompleteMultipartUploadRequest::default()
+            };
+
+            match client.complete_multipart_upload(&complete_request)
+            {
+                Ok(_) => return Ok(()),
+                Err(error) => UploadError::Request(error.to_string())
+            }
+        }
+        Err(error) => error
+    };
+
+    // The upload is aborted so the parts already uploaded don't keep accruing storage charges
+    let abort_request = AbortMultipartUploadRequest
+    {
+        bucket: request.bucket.to_owned(),
+        key: request.key.to_owned(),
+        upload_id: upload_id.to_owned(),
+        ..AbortMultipartUploadRequest::default()
+    };
+
+    match client.abort_multipart_upload(&abort_request)
+    {
+        Ok(_) => return Err(error),
+        Err(abort_error) => return Err(UploadError::Request(format!("{} (aborting the multipart upload also failed: {})", error, abort_error)))
+    }
+}
+
+/**
+ * Upload a local file to an s3 bucket location, returns the number of attempts the upload took and the number of bytes uploaded
+ */
+pub fn local_file_upload_to_bucket(client: &dyn ObjectStore, file: &LocalFile, local_path: &String, bucket_name: &String, options: &UploadOptions) -> Result<(u32, u64), UploadError>
+{
+    let mut request = PutObjectRequest
+    {
+        bucket: bucket_name.to_owned(),
+        key: local_file_get_key(file, local_path, options),
+        acl: Some(options.acl.to_owned()),
+        cache_control: options.cache_control.to_owned(),
+        storage_class: options.storage_class.to_owned(),
+        server_side_encryption: options.server_side_encryption.to_owned(),
+        ssekms_key_id: options.ssekms_key_id.to_owned(),
+        content_type: Some(local_file_get_mime(file, &options.mime_overrides, options.sniff_mime)),
+        ..PutObjectRequest::default()
+    };
+
+    // The modification time of the source file is kept in seconds, a --metadata or --header with the same key replaces it
+    if let Some(mtime) = file.mtime
+    {
+        request.metadata.get_or_insert_with(HashMap::new).insert(String::from("src-mtime"), (mtime / 1000000000).to_string());
+    }
+
+    for &(ref name, ref value) in &options.headers
+    {
+        put_object_request_set_header(&mut request, name, value);
+    }
+
+    // The first rule matching the file replaces the options and headers it sets
+    if let Some(rule) = local_file_get_rule(file, local_path, &options.rules)
+    {
+        if rule.acl.is_some()
+        {
+            request.acl = rule.acl.to_owned();
+        }
+
+        if rule.cache_control.is_some()
+        {
+            request.cache_control = rule.cache_control.to_owned();
+        }
+
+        if rule.content_type.is_some()
+        {
+            request.content_type = rule.content_type.to_owned();
+        }
+    }
+
+    // The headers the S3 client doesn't send are only registered with the client while the file is uploaded, as its dispatcher adds them to the requests for the key
+    let multipart = local_file_get_size(file).map(|size| size > options.multipart_threshold).unwrap_or(false);
+    let request_key = request.key.to_owned();
+    client.set_object_headers(bucket_name, &request_key, &Some(put_object_request_get_unsent_headers(&request, multipart)));
+    let result = local_file_send_to_bucket(client, file, request, options);
+    client.set_object_headers(bucket_name, &request_key, &None);
+
+    return result;
+}
+
+/**
+ * Get the headers of an upload request that the S3 client doesn't send, its metadata and, as CreateMultipartUpload has no tagging field, the tagging of a multipart upload
+ */
+fn put_object_request_get_unsent_headers(request: &PutObjectRequest, multipart: bool) -> HashMap<String, String>
+{
+    let mut headers: HashMap<String, String> = HashMap::new();
+
+    if let Some(ref metadata) = request.metadata
+    {
+        for (key, value) in metadata
+        {
+            headers.insert(format!("x-amz-meta-{}", key), value.to_owned());
+        }
+    }
+
+    // A PutObject request already sends its tagging, adding it again would send the header twice
+    if let (true, &Some(ref tagging)) = (multipart, &request.tagging)
+    {
+        headers.insert(String::from("x-amz-tagging"), tagging.to_owned());
+    }
+
+    return headers;
+}
+
+/**
+ * Send the contents of a local file with a prepared upload request, retrying transient errors
+ */
+fn local_file_send_to_bucket(client: &dyn ObjectStore, file: &LocalFile, mut request: PutObjectRequest, options: &UploadOptions) -> Result<(u32, u64), UploadError>
+{
+    // Files above the multipart threshold are uploaded in parts so only one part is held in memory at a time
+    let file_size = local_file_get_size(file)?;
+
+    if file_size > options.multipart_threshold
+    {
+        local_file_upload_multipart(client, file, &request, options.max_retries)?;
+        return Ok((1, file_size));
+    }
+
+    // Text files are compressed if the gzip parameter is set, keeping their original content type
+    if options.gzip && mime_type_is_compressible(&local_file_get_mime(file, &options.mime_overrides, options.sniff_mime))
+    {
+        request.body = Some(local_file_get_gzip(file)?);
+        request.content_encoding = Some(String::from("gzip"));
+    }
+    else
+    {
+        let mut file_handle = File::open(&file.path)?;
+        let mut contents: Vec<u8> = Vec::new();
+
+        // The whole file is buffered as rusoto's PutObjectRequest body only accepts a Vec<u8>, not a stream
+        file_handle.read_to_end(&mut contents)?;
+        request.body = Some(contents);
+    }
+
+    // S3 rejects the upload with BadDigest if the body it received doesn't match, the checksum is reused from the file unless it has none, e.g. for the checksums file
+    request.content_md5 = Some(get_content_md5(&file.checksum, request.body.as_ref().unwrap()));
+
+    let mut attempt = 1;
+
+    // The bytes uploaded are those of the body as sent, so a compressed file counts its compressed size
+    let body_size = request.body.as_ref().unwrap().len() as u64;
+
+    loop
+    {
+        match client.put_object(&request)
+        {
+            Ok(_) =>
+            {
+                return Ok((attempt, body_size));
+            }
+            Err(ref error) if attempt <= options.max_retries && upload_error_is_transient(error) =>
+            {
+                retry_backoff(attempt);
+                attempt += 1;
+            }
+            Err(error) =>
+            {
+                return Err(UploadError::Request(put_object_error_describe(&error)));
+            }
+        }
+    }
+}
+
+/**
+ * Print the result of uploading a local file
+ */
+pub fn print_upload_result(file: &LocalFile, key: &String, bucket_name: &String, result: &Result<(u32, u64), UploadError>, progress: &str)
+{
+    let status = match *result
+    {
+        Ok((1, _)) => stderr_paint(Green, "DONE"),
+        Ok((attempts, _)) => format!("{} {}", stderr_paint(Green, "DONE"), stderr_paint(Yellow, format!("(after {} attempts)", attempts))),
+        Err(ref error) => format!("{}: {}", stderr_paint(Red, "Error"), error)
+    };
+
+    // The whole line is written at once, as a line split across writes can be broken up by the output of another upload worker
+    eprintln!("{}{} \"{}\" to \"{}/{}\"... {}", progress, stderr_paint(Yellow, "Uploading"), file.path.display(), bucket_name, key, status);
+}
+
+/**
+ * Get a value escaped for the message of a GitHub Actions workflow command, which ends at a newline
+ */
+fn github_escape_message(value: &str) -> String
+{
+    return value.replace("%", "%25").replace("\r", "%0D").replace("\n", "%0A");
+}
+
+/**
+ * Print the result of uploading a local file as a GitHub Actions annotation on stdout, a notice when it was uploaded or an error when it failed
+ */
+pub fn print_github_upload_result(file: &LocalFile, key: &String, bucket_name: &String, result: &Result<(u32, u64), UploadError>)
+{
+    let upload = format!("\"{}\" to \"{}/{}\"", file.path.display(), bucket_name, key);
+
+    match *result
+    {
+        Ok((1, _)) => println!("::notice title=Uploaded::{}", github_escape_message(&upload)),
+        Ok((attempts, _)) => println!("::notice title=Uploaded::{}", github_escape_message(&format!("{} (after {} attempts)", upload, attempts))),
+        Err(ref error) => println!("::error title=Upload failed::{}", github_escape_message(&format!("{}: {}", upload, error)))
+    }
+}
+
+/**
+ * Struct for the progress of a batch of uploads, its lock is also held while printing upload results
+ */
+struct UploadProgress
+{
+    files_done: usize,
+    files_total: usize,
+    bytes_done: u64,
+    bytes_total: u64
+}
+
+/**
+ * Get a readable size from a number of bytes, e.g. 512 B, 1.5 KB or 20.3 MB
+ */
+pub fn format_size(bytes: u64) -> String
+{
+    let units = ["B", "KB", "MB", "GB", "TB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+
+    while size >= 1024.0 && unit < units.len() - 1
+    {
+        size /= 1024.0;
+        unit += 1;
+    }
+
+    if unit == 0
+    {
+        return format!("{} {}", bytes, units[unit]);
+    }
+
+    return format!("{:.1} {}", size, units[unit]);
+}
+
+/**
+ * Upload local files to an s3 bucket using a number of worker threads, returns the files that failed to upload and the number of bytes uploaded. Each uploaded file is added to the journal, if one is given
+ */
+pub fn local_files_upload_to_bucket(client: &Arc<dyn ObjectStore>, files: Vec<LocalFile>, local_path: &String, bucket_name: &String, options: &UploadOptions, memory_budget: &Arc<MemoryBudget>, concurrency: usize, output_format: OutputFormat, journal_path: &Option<PathBuf>) -> (Vec<LocalFile>, u64)
+{
+    let uploaded_bytes: Arc<Mutex<u64>> = Arc::new(Mutex::new(0));
+    let progress = Arc::new(Mutex::new(UploadProgress
+    {
+        files_done: 0,
+        files_total: files.len(),
+        bytes_done: 0,
+        bytes_total: files.iter().map(|file| local_file_get_size(file).unwrap_or(0)).sum()
+    }));
+
+    let queue: Arc<Mutex<VecDeque<LocalFile>>> = Arc::new(Mutex::new(files.into_iter().collect()));
+    let failed_files: Arc<Mutex<Vec<LocalFile>>> = Arc::new(Mutex::new(Vec::new()));
+    let mut workers = Vec::new();
+
+    for _ in 0..concurrency
+    {
+        let client = client.clone();
+        let queue = queue.clone();
+        let failed_files = failed_files.clone();
+        let uploaded_bytes = uploaded_bytes.clone();
+        let local_path = local_path.clone();
+        let bucket_name = bucket_name.clone();
+        let options = options.clone();
+        let memory_budget = memory_budget.clone();
+        let progress = progress.clone();
+        let journal_path = journal_path.clone();
+
+        workers.push(thread::spawn(move ||
+        {
+            loop
+            {
+                let file = match queue.lock().unwrap().pop_front()
+                {
+                    Some(file) => file,
+                    None => break
+                };
+
+                let reserved = memory_budget_acquire(&memory_budget, local_file_get_upload_size(&file, options.multipart_threshold));
+
+                let result = local_file_upload_to_bucket(&*client, &file, &local_path, &bucket_name, &options);
+
+                // Each result is printed as a whole line while holding the progress lock so concurrent uploads don't interleave
+                {
+                    let mut progress = progress.lock().unwrap();
+                    progress.files_done += 1;
+                    progress.bytes_done += local_file_get_size(&file).unwrap_or(0);
+
+                    if output_format == OutputFormat::Quiet
+                    {
+                        if let Err(ref error) = result
+                        {
+                            print_error(&format!("Error \"{}\": {}", file.path.display(), error));
+                        }
+                    }
+                    else if output_format == OutputFormat::Github
+                    {
+                        print_github_upload_result(&file, &local_file_get_key(&file, &local_path, &options), &bucket_name, &result);
+                    }
+                    else
+                    {
+                        let progress_counter = format!("[{}/{}, {} of {}] ", progress.files_done, progress.files_total, format_size(progress.bytes_done), format_size(progress.bytes_total));
+                        print_upload_result(&file, &local_file_get_key(&file, &local_path, &options), &bucket_name, &result, &progress_counter);
+                    }
+
+                    // The journal is written while holding the progress lock too, so the workers append whole lines one at a time
+                    if let (&Ok(_), &Some(ref journal_path)) = (&result, &journal_path)
+                    {
+                        if let Err(error) = local_journal_append(journal_path, &file, &local_path, &bucket_name, &options)
+                        {
+                            eprintln!("{}: \"{}\" could not be added to the journal: {}", stderr_paint(Yellow, "Warning"), file.path.display(), error);
+                        }
+                    }
+                }
+
+                match result
+                {
+                    Ok((_, bytes)) => *uploaded_bytes.lock().unwrap() += bytes,
+                    Err(_) => failed_files.lock().unwrap().push(file)
+                }
+
+                memory_budget_release(&memory_budget, reserved);
+            }
+        }));
+    }
+
+    for worker in workers
+    {
+        worker.join().expect("Upload worker panicked");
+    }
+
+    let failed_files = failed_files.lock().unwrap().clone();
+    let uploaded_bytes = *uploaded_bytes.lock().unwrap();
+
+    return (failed_files, uploaded_bytes);
+}
+
+/**
+ * Escape a value for the text of an XML element, as the S3 client puts the keys of a DeleteObjects request into its body as they are
+ */
+fn xml_escape(value: &str) -> String
+{
+    return value.replace("&", "&amp;").replace("<", "&lt;").replace(">", "&gt;").replace("\"", "&quot;").replace("'", "&apos;");
+}
+
+/**
+ * Delete objects from an s3 bucket in batches of up to 1000 keys, returning the result for each key in the order given
+ */
+pub fn delete_bucket_objects(client: &dyn ObjectStore, bucket_name: &String, keys: &Vec<String>) -> Vec<(String, Result<(), String>)>
+{
+    let mut results: Vec<(String, Result<(), String>)> = Vec::new();
+
+    for batch in keys.chunks(DELETE_BATCH_SIZE)
+    {
+        let request = DeleteObjectsRequest
+        {
+            bucket: bucket_name.to_owned(),
+            delete: Delete
+            {
+                objects: batch.iter().map(|key| ObjectIdentifier { key: xml_escape(key), version_id: None }).collect(),
+
+                // Quiet mode only lists the keys that could not be deleted, every other key in the batch was deleted
+                quiet: Some(true)
+            },
+            ..DeleteObjectsRequest::default()
+        };
+
+        match client.delete_objects(&request)
+        {
+            Ok(response) =>
+            {
+                let errors = response.errors.unwrap_or(Vec::new());
+
+                for key in batch
+                {
+                    match errors.iter().find(|error| error.key.as_ref() == Some(key))
+                    {
+                        Some(error) =>
+                        {
+                            let code = error.code.to_owned().unwrap_or(String::from("Error"));
+                            results.push((key.to_owned(), Err(match error.message { Some(ref message) => format!("{}: {}", code, message), None => code })));
+                        }
+                        None =>
+                        {
+                            results.push((key.to_owned(), Ok(())));
+                        }
+                    }
+                }
+            }
+            Err(error) =>
+            {
+                // The whole batch failed, so none of its keys were deleted
+                let error = error.to_string();
+
+                for key in batch
+                {
+                    results.push((key.to_owned(), Err(error.to_owned())));
+                }
+            }
+        }
+    }
+
+    return results;
+}
+
+/**
+ * Get the directories under a local path that have no files to upload and no other directories, skipping the directories get_local_files skips. Symlinked directories aren't followed
+ */
+pub fn get_empty_local_directories(root_path: &String, local_path: &Path, files: &Vec<LocalFile>, ignored_directories: &Vec<String>, ignore_patterns: &Vec<String>, skip_hidden: bool, empty_directories: &mut Vec<PathBuf>)
+{
+    let mut has_directories = false;
+
+    if let Ok(entries) = fs::read_dir(local_path)
+    {
+        for entry in entries.filter_map(|entry| entry.ok())
+        {
+            if !entry.file_type().map(|file_type| file_type.is_dir()).unwrap_or(false)
+            {
+                continue;
+            }
+
+            let name = entry.file_name().to_string_lossy().into_owned();
+            let directory = LocalFile { path: entry.path(), checksum: String::from(""), mtime: None };
+
+            if (skip_hidden && name.starts_with(".")) || ignored_directories.contains(&name) || path_matches_ignore_patterns(&local_file_get_relative_path(&directory, root_path), true, ignore_patterns)
+            {
+                continue;
+            }
+
+            has_directories = true;
+            get_empty_local_directories(root_path, &entry.path(), files, ignored_directories, ignore_patterns, skip_hidden, empty_directories);
+        }
+    }
+
+    if !has_directories && local_path != Path::new(root_path) && !files.iter().any(|file| file.path.parent() == Some(local_path))
+    {
+        empty_directories.push(local_path.to_owned());
+    }
+}
+
+/**
+ * Get the key of the placeholder object of a local directory, its key path followed by a slash, unless the strip_components option removes the whole path
+ */
+pub fn local_directory_get_placeholder_key(directory: &Path, local_path: &String, options: &UploadOptions) -> Option<String>
+{
+    let directory = LocalFile { path: directory.to_owned(), checksum: String::from(""), mtime: None };
+
+    if !local_file_has_key_path(&directory, local_path, options.strip_components)
+    {
+        return None;
+    }
+
+    return Some(format!("{}/", local_file_get_key(&directory, local_path, options)));
+}
+
+/**
+ * Upload the zero byte placeholder object of an empty directory, so S3 browsers show the directory
+ */
+pub fn bucket_put_directory_placeholder(client: &dyn ObjectStore, bucket_name: &String, key: &String, options: &UploadOptions) -> Result<(), String>
+{
+    let request = PutObjectRequest
+    {
+        bucket: bucket_name.to_owned(),
+        key: key.to_owned(),
+        acl: Some(options.acl.to_owned()),
+        content_type: Some(String::from("application/x-directory")),
+        content_md5: Some(get_content_md5("", &[])),
+        body: Some(Vec::new()),
+        ..PutObjectRequest::default()
+    };
+
+    return client.put_object(&request).map(|_| ()).map_err(|error| put_object_error_describe(&error));
+}
+
+/**
+ * Print the result of uploading the placeholder object of an empty directory
+ */
+pub fn print_directory_placeholder_result(bucket_name: &String, key: &String, result: &Result<(), String>)
+{
+    eprint!("{} \"{}/{}\"...", stderr_paint(Green, "Creating folder"), bucket_name, key);
+
+    match *result
+    {
+        Ok(_) =>
+        {
+            eprintln!(" {}", stderr_paint(Green, "DONE"));
+        }
+        Err(ref error) =>
+        {
+            eprintln!(" {}: {}", stderr_paint(Red, "Error"), error);
+        }
+    }
+}
+
+/**
+ * Print the result of deleting an object from an s3 bucket
+ */
+pub fn print_delete_result(bucket_name: &String, key: &String, result: &Result<(), String>)
+{
+    eprint!("{} \"{}/{}\"...", stderr_paint(Red, "Deleting"), bucket_name, key);
+
+    match *result
+    {
+        Ok(_) =>
+        {
+            eprintln!(" {}", stderr_paint(Green, "DONE"));
+        }
+        Err(ref error) =>
+        {
+            eprintln!(" {}: {}", stderr_paint(Red, "Error"), error);
+        }
+    }
+}
+
+/**
+ * Get the local path a bucket object is downloaded to, or None if its key can't be a file under the local path
+ */
+fn bucket_key_get_local_path(key: &String, prefix: &String, local_path: &String) -> Option<PathBuf>
+{
+    let relative_path = &key[prefix.len()..];
+    let mut path = PathBuf::from(local_path);
+
+    // Keys ending in / are folder placeholders, and keys with empty, . or .. segments would be written outside the local path
+    for segment in relative_path.split("/")
+    {
+        if segment.is_empty() || segment == "." || segment == ".." || segment.contains("\\")
+        {
+            return None;
+        }
+
+        path.push(segment);
+    }
+
+    return Some(path);
+}
+
+/**
+ * Download an object from an s3 bucket to a local path, creating its directories as needed
+ */
+fn bucket_object_download(client: &dyn ObjectStore, bucket_name: &String, key: &String, path: &Path) -> Result<(), String>
+{
+    let request = GetObjectRequest
+    {
+        bucket: bucket_name.to_owned(),
+        key: key.to_owned(),
+        ..GetObjectRequest::default()
+    };
+
+    let response = client.get_object(&request).map_err(|error| get_object_error_describe(&error))?;
+
+    // An empty object has no body
+    let mut contents = response.body.unwrap_or(Vec::new());
+
+    // Files uploaded with the gzip parameter are stored compressed, so they are decompressed to their original contents
+    if response.content_encoding.as_ref().map(|encoding| encoding.as_str()) == Some("gzip")
+    {
+        let mut decompressed: Vec<u8> = Vec::new();
+        GzDecoder::new(&contents[..]).and_then(|mut decoder| decoder.read_to_end(&mut decompressed)).map_err(|error| error.to_string())?;
+        contents = decompressed;
+    }
+
+    if let Some(parent) = path.parent()
+    {
+        fs::create_dir_all(parent).map_err(|error| error.to_string())?;
+    }
+
+    let mut file_handle = File::create(path).map_err(|error| error.to_string())?;
+    file_handle.write_all(&contents).map_err(|error| error.to_string())?;
+
+    return Ok(());
+}
+
+/**
+ * Print the result of downloading an object from an s3 bucket
+ */
+fn print_download_result(bucket_name: &String, key: &String, path: &Path, result: &Result<(), String>)
+{
+    eprint!("{} \"{}/{}\" to \"{}\"...", stderr_paint(Yellow, "Downloading"), bucket_name, key, path.display());
+
+    match *result
+    {
+        Ok(_) =>
+        {
+            eprintln!(" {}", stderr_paint(Green, "DONE"));
+        }
+        Err(ref error) =>
+        {
+            eprintln!(" {}: {}", stderr_paint(Red, "Error"), error);
+        }
+    }
+}
+
+/**
+ * Check if a downloaded local file matches the checksum recorded for its object, hashing it with the algorithm the checksums were recorded with
+ *
+ * A file uploaded with the gzip parameter has the checksum of its compressed upload body, while it is decompressed on download, so a compressible file that doesn't match is compressed the same way again before it is taken as changed
+ */
+fn local_file_matches_downloaded_checksum(file: &LocalFile, checksum: &String, algorithm: HashAlgorithm) -> bool
+{
+    if local_file_get_hash(file, algorithm).ok().map_or(false, |local_checksum| checksums_match(&local_checksum, checksum))
+    {
+        return true;
+    }
+
+    if local_file_get_size(file).map_or(true, |size| size > MULTIPART_THRESHOLD) || !mime_type_is_compressible(&local_file_get_mime(file, &HashMap::new(), false))
+    {
+        return false;
+    }
+
+    return local_file_get_gzip_hash(file, algorithm).ok().map_or(false, |local_checksum| checksums_match(&local_checksum, checksum));
+}
+
+/**
+ * Download the objects of an s3 bucket under a prefix to a local path, skipping the files whose checksum already matches, returning false if any operation failed
+ */
+pub fn run_download(client: &dyn ObjectStore, bucket_name: &String, prefix: &String, checksums_name: &String, local_path: &String, dry_run: bool) -> bool
+{
+    let mut bucket_objects: Vec<Object> = Vec::new();
+
+    if !get_bucket_objects(client, bucket_name, prefix, &mut bucket_objects)
+    {
+        return false;
+    }
+
+    // The ETags stand in for the checksums file when the bucket has none, as they do for an upload
+    let checksums = match get_bucket_object_checksums(client, bucket_name, prefix, checksums_name, &bucket_objects)
+    {
+        Some(checksums) => checksums,
+        None => get_bucket_etag_checksums(&bucket_objects, prefix, checksums_name)
+    };
+
+    let checksums_key = format!("{}{}", prefix, checksums_name);
+    let mut downloads: Vec<(String, PathBuf)> = Vec::new();
+    let mut skipped_count = 0;
+
+    eprintln!("");
+
+    for object in &bucket_objects
+    {
+        let key = match object.key
+        {
+            Some(ref key) if key != &checksums_key && key.starts_with(prefix.as_str()) => key,
+            _ => continue
+        };
+
+        let path = match bucket_key_get_local_path(key, prefix, local_path)
+        {
+            Some(path) => path,
+            None =>
+            {
+                if !key.ends_with("/")
+                {
+                    eprintln!("{}", stderr_paint(Yellow, format!("Skipping \"{}/{}\", its key can't be used as a local path", bucket_name, key)));
+                }
+
+                continue;
+            }
+        };
+
+        // A local file is kept when its checksum matches the recorded one, objects without a checksum are always downloaded
+        if path.is_file()
+        {
+            if let Some(checksum) = checksums.files.get(&key[prefix.len()..])
+            {
+                let file = LocalFile { path: path.to_owned(), checksum: String::from(""), mtime: None };
+
+                if local_file_matches_downloaded_checksum(&file, checksum, checksums.algorithm)
+                {
+                    skipped_count += 1;
+                    continue;
+                }
+            }
+        }
+
+        downloads.push((key.to_owned(), path));
+    }
+
+    if downloads.len() == 0
+    {
+        eprintln!("{}", stderr_paint(Green, format!("No files to download, {} already up to date", skipped_count)));
+        return true;
+    }
+
+    if dry_run
+    {
+        for &(ref key, ref path) in &downloads
+        {
+            println!("{} \"{}/{}\" to \"{}\"", Yellow.paint("Would download"), bucket_name, key, path.display());
+        }
+
+        eprintln!("\n{}", stderr_paint(Yellow, "DRY RUN - no files downloaded"));
+        return true;
+    }
+
+    let mut failed_count = 0;
+
+    for &(ref key, ref path) in &downloads
+    {
+        let result = bucket_object_download(client, bucket_name, key, path);
+        print_download_result(bucket_name, key, path, &result);
+
+        if result.is_err()
+        {
+            failed_count += 1;
+        }
+    }
+
+    if failed_count > 0
+    {
+        eprintln!("\n{}", stderr_paint(Red, format!("DOWNLOAD FAILED - {} of {} files could not be downloaded", failed_count, downloads.len())));
+        return false;
+    }
+
+    eprintln!("\n{}", stderr_paint(Green, "DOWNLOAD COMPLETE"));
+    return true;
+}
+
+/**
+ * Create the local file checksums, writing them to a temporary file that is renamed into place so an interrupted run can't leave a partly written checksums file
+ */
+pub fn local_file_create_checksums(files: &Vec<LocalFile>, local_path: &String, checksums_name: &String, options: &UploadOptions) -> io::Result<LocalFile>
+{
+    // The checksums are sorted by path so the file is stable between runs
+    let checksums = local_file_get_checksums(files, local_path, options);
+
+    return local_file_write_checksums(&checksums, local_path, checksums_name, options.checksums_format);
+}
+
+/**
+ * Write checksums to the local checksums file in the given line format, through a temporary file that is renamed into place
+ */
+pub fn local_file_write_checksums(checksums: &Checksums, local_path: &String, checksums_name: &String, format: ChecksumsFormat) -> io::Result<LocalFile>
+{
+    let mut path = PathBuf::from(local_path);
+    path.push(checksums_name);
+
+    let mut temporary_path = PathBuf::from(local_path);
+    temporary_path.push(format!(".{}.tmp", checksums_name));
+
+    {
+        let mut checksums_file = File::create(&temporary_path)?;
+
+        // The header line records the hash algorithm, so a run with another algorithm knows not to compare against these checksums. md5sum -c --strict fails on it, so the md5sum format leaves it out and the algorithm is told from the length of the checksums
+        if format == ChecksumsFormat::Path
+        {
+            checksums_file.write_all(format!("{}{}\n", CHECKSUMS_HASH_HEADER, hash_algorithm_name(checksums.algorithm)).as_bytes())?;
+        }
+
+        // The checksum and size columns are read back from the end of the line, so the path can contain spaces. GNU md5sum lines have no room for the size and modification time, md5sum -c would take them as part of the path
+        for (relative_path, checksum) in &checksums.files
+        {
+            let checksums_line = match (format, checksums.sizes_mtimes.get(relative_path))
+            {
+                (ChecksumsFormat::Md5sum, _) =>
+                {
+                    let (escaped, escaped_path) = md5sum_escape_path(relative_path);
+                    format!("{}{}  {}\n", if escaped { "\\" } else { "" }, checksum, escaped_path)
+                }
+                (ChecksumsFormat::Path, Some(&(size, mtime))) => format!("{} {} {} {}\n", relative_path, checksum, size, mtime),
+                (ChecksumsFormat::Path, None) => format!("{} {}\n", relative_path, checksum)
+            };
+
+            checksums_file.write_all(checksums_line.as_bytes())?;
+        }
+
+        checksums_file.sync_all()?;
+    }
+
+    fs::rename(&temporary_path, &path)?;
+
+    return Ok(LocalFile { path: path, checksum: String::from(""), mtime: None });
+}
+
+/**
+ * Get the checksums for a list of local files, with the size and modification time of the files that aren't compressed
+ */
+pub fn local_file_get_checksums(files: &Vec<LocalFile>, local_path: &String, options: &UploadOptions) -> Checksums
+{
+    let mut file_checksums = BTreeMap::new();
+    let mut file_sizes_mtimes = BTreeMap::new();
+
+    for file in files
+    {
+        let relative_path = local_file_get_key_path(file, local_path, options.strip_components);
+
+        // Compressed files are left without a size and modification time, so turning gzip off hashes them again instead of reusing their compressed checksums
+        if !local_file_is_gzipped(file, options)
+        {
+            if let (Some(mtime), Ok(size)) = (local_file_get_mtime(file), local_file_get_size(file))
+            {
+                file_sizes_mtimes.insert(relative_path.to_owned(), (size, mtime));
+            }
+        }
+
+        file_checksums.insert(relative_path, file.checksum.to_owned());
+    }
+
+    return Checksums { files: file_checksums, sizes_mtimes: file_sizes_mtimes, algorithm: options.hash_algorithm };
+}
+
+/**
+ * Get the upload options of the checksums file from the options of the uploaded files
+ */
+pub fn checksums_upload_options(options: &UploadOptions) -> UploadOptions
+{
+    // The headers of the files aren't applied to the checksums, only the tags set for them so automation can find them
+    let checksums_headers: Vec<(String, String)> = options.checksums_tagging.iter().map(|tagging| (String::from("x-amz-tagging"), tagging.to_owned())).collect();
+
+    // The checksums use the same ACL as the uploaded files, but are never cached so a CDN can't serve stale checksums and stay in the standard storage class so they are cheap to read. They are encrypted the same way as the files so a bucket policy requiring encryption doesn't reject them
+    return UploadOptions { prefix: options.prefix.to_owned(), acl: options.acl.to_owned(), cache_control: Some(String::from("no-cache")), gzip: false, storage_class: None, server_side_encryption: options.server_side_encryption.to_owned(), ssekms_key_id: options.ssekms_key_id.to_owned(), headers: checksums_headers, mime_overrides: HashMap::new(), sniff_mime: false, hash_algorithm: options.hash_algorithm, checksums_format: options.checksums_format, multipart_threshold: MULTIPART_THRESHOLD, max_retries: options.max_retries, checksums_tagging: None, strip_components: 0, rules: Vec::new() };
+}
+
+/**
+ * Get the checksums without the entries of files that no longer exist locally, along with the paths of the entries removed. The entries of paths the filters leave out are kept, as their files weren't looked at
+ */
+pub fn checksums_prune(checksums: &Checksums, files: &Vec<LocalFile>, local_path: &String, options: &UploadOptions, filters: &PathFilters) -> (Checksums, Vec<String>)
+{
+    let local_paths: HashSet<String> = files.iter().map(|file| local_file_get_key_path(file, local_path, options.strip_components)).collect();
+    let mut pruned_checksums = checksums.clone();
+    let mut pruned_paths: Vec<String> = Vec::new();
+
+    for path in checksums.files.keys()
+    {
+        if !local_paths.contains(path) && !path_is_filtered_out(path, filters)
+        {
+            pruned_checksums.files.remove(path);
+            pruned_checksums.sizes_mtimes.remove(path);
+            pruned_paths.push(path.to_owned());
+        }
+    }
+
+    return (pruned_checksums, pruned_paths);
+}
+
+/**
+ * Get the checksums without the entries of files whose objects are no longer in the bucket, along with the paths of the entries removed
+ */
+pub fn checksums_prune_orphans(checksums: &Checksums, bucket_objects: &Vec<Object>, prefix: &String) -> (Checksums, Vec<String>)
+{
+    let bucket_keys: HashSet<&String> = bucket_objects.iter().filter_map(|object| object.key.as_ref()).collect();
+    let mut pruned_checksums = checksums.clone();
+    let mut pruned_paths: Vec<String> = Vec::new();
+
+    for path in checksums.files.keys()
+    {
+        if !bucket_keys.contains(&&format!("{}{}", prefix, path))
+        {
+            pruned_checksums.files.remove(path);
+            pruned_checksums.sizes_mtimes.remove(path);
+            pruned_paths.push(path.to_owned());
+        }
+    }
+
+    return (pruned_checksums, pruned_paths);
+}
+
+/**
+ * Get checksums with the entries of new checksums added to them or replacing theirs, or only the new checksums if they use another hash algorithm
+ */
+pub fn checksums_merge(checksums: &Checksums, new_checksums: &Checksums) -> Checksums
+{
+    if checksums.algorithm != new_checksums.algorithm
+    {
+        return new_checksums.clone();
+    }
+
+    let mut merged_checksums = checksums.clone();
+
+    for (path, checksum) in &new_checksums.files
+    {
+        merged_checksums.files.insert(path.to_owned(), checksum.to_owned());
+
+        match new_checksums.sizes_mtimes.get(path)
+        {
+            Some(size_mtime) => merged_checksums.sizes_mtimes.insert(path.to_owned(), size_mtime.to_owned()),
+            None => merged_checksums.sizes_mtimes.remove(path)
+        };
+    }
+
+    return merged_checksums;
+}
+
+/**
+ * Get new checksums with the entries the held files have in the current checksums kept, so the changes that weren't uploaded are found again on the next run
+ */
+pub fn checksums_keep_held(checksums: &Checksums, new_checksums: &Checksums, held_files: &Vec<LocalFile>, local_path: &String, options: &UploadOptions) -> Checksums
+{
+    let mut kept_checksums = new_checksums.clone();
+
+    for file in held_files
+    {
+        let path = local_file_get_key_path(file, local_path, options.strip_components);
+
+        kept_checksums.files.remove(&path);
+        kept_checksums.sizes_mtimes.remove(&path);
+
+        // A held new file has no entry to keep, and entries of another hash algorithm can't be kept with these
+        if checksums.algorithm == new_checksums.algorithm
+        {
+            if let Some(checksum) = checksums.files.get(&path)
+            {
+                kept_checksums.files.insert(path.to_owned(), checksum.to_owned());
+            }
+
+            if let Some(size_mtime) = checksums.sizes_mtimes.get(&path)
+            {
+                kept_checksums.sizes_mtimes.insert(path.to_owned(), size_mtime.to_owned());
+            }
+        }
+    }
+
+    return kept_checksums;
+}
+
+/**
+ * Get new checksums with the entries of the paths the filters leave out kept from the checksums, so a filtered run doesn't drop the entries of the files it didn't look at
+ */
+pub fn checksums_keep_filtered_out(checksums: &Checksums, new_checksums: &Checksums, filters: &PathFilters) -> Checksums
+{
+    let mut kept_checksums = new_checksums.clone();
+
+    // Entries of another hash algorithm can't be kept with these
+    if checksums.algorithm != new_checksums.algorithm
+    {
+        return kept_checksums;
+    }
+
+    for (path, checksum) in &checksums.files
+    {
+        if !kept_checksums.files.contains_key(path) && path_is_filtered_out(path, filters)
+        {
+            kept_checksums.files.insert(path.to_owned(), checksum.to_owned());
+
+            if let Some(size_mtime) = checksums.sizes_mtimes.get(path)
+            {
+                kept_checksums.sizes_mtimes.insert(path.to_owned(), size_mtime.to_owned());
+            }
+        }
+    }
+
+    return kept_checksums;
+}
+
+/**
+ * Delete the local file checksums
+ */
+pub fn local_file_delete_checksums(local_path: &String, checksums_name: &String) -> io::Result<()>
+{
+    let mut path = PathBuf::from(local_path);
+    path.push(checksums_name);
+
+    return remove_file(path);
+}
+
+/**
+ * Get the path of the local journal of the files uploaded by a run that hasn't uploaded its checksums yet
+ */
+pub fn local_journal_get_path(local_path: &String, checksums_name: &String) -> PathBuf
+{
+    let mut path = PathBuf::from(local_path);
+    path.push(format!(".{}.journal", checksums_name));
+
+    return path;
+}
+
+/**
+ * Append the checksum of an uploaded file to the local journal, which starts with the bucket and hash algorithm it was written for
+ */
+pub fn local_journal_append(journal_path: &Path, file: &LocalFile, local_path: &String, bucket_name: &String, options: &UploadOptions) -> io::Result<()>
+{
+    let mut journal_file = OpenOptions::new().create(true).append(true).open(journal_path)?;
+    let mut journal_lines = String::new();
+
+    if journal_file.metadata()?.len() == 0
+    {
+        journal_lines.push_str(&format!("{}{}/{}\n", JOURNAL_BUCKET_HEADER, bucket_name, options.prefix));
+        journal_lines.push_str(&format!("{}{}\n", CHECKSUMS_HASH_HEADER, hash_algorithm_name(options.hash_algorithm)));
+    }
+
+    journal_lines.push_str(&format!("{} {}\n", local_file_get_key_path(file, local_path, options.strip_components), file.checksum));
+
+    // Each line is written at once so an interrupted run leaves at most its last line incomplete, which isn't parsed back
+    return journal_file.write_all(journal_lines.as_bytes());
+}
+
+/**
+ * Get the checksums of the files in the local journal, if there is one written for the same bucket and prefix
+ */
+pub fn local_journal_read(journal_path: &Path, bucket_name: &String, prefix: &String) -> Option<Checksums>
+{
+    let mut contents = String::new();
+    File::open(journal_path).and_then(|mut file| file.read_to_string(&mut contents)).ok()?;
+
+    if contents.lines().next() != Some(&format!("{}{}/{}", JOURNAL_BUCKET_HEADER, bucket_name, prefix))
+    {
+        return None;
+    }
+
+    return Some(parse_checksums(&contents));
+}
+
+/**
+ * Delete the local journal, once the checksums it was kept for are in the bucket
+ */
+pub fn local_journal_delete(journal_path: &Path) -> io::Result<()>
+{
+    match remove_file(journal_path)
+    {
+        Err(ref error) if error.kind() == io::ErrorKind::NotFound => return Ok(()),
+        result => return result
+    }
+}
+
+/**
+ * Get objects in an s3 bucket, returning false if the bucket could not be listed
+ */
+pub fn get_bucket_objects(client: &dyn ObjectStore, bucket_name: &String, prefix: &String, objects: &mut Vec<Object>) -> bool
+{
+    let mut continuation_token: Option<String> = None;
+
+    // Each response holds at most 1000 objects, so the listing is followed page by page
+    loop
+    {
+        let request = ListObjectsV2Request
+        {
+            bucket: bucket_name.to_owned(),
+            prefix: if prefix.is_empty() { None } else { Some(prefix.to_owned()) },
+            continuation_token: continuation_token.clone(),
+            ..ListObjectsV2Request::default()
+        };
+
+        match client.list_objects_v2(&request)
+        {
+            Ok(response) =>
+            {
+                // An empty bucket has no contents at all
+                for object in response.contents.unwrap_or(Vec::new())
+                {
+                    objects.push(object);
+                }
+
+                if response.is_truncated != Some(true) || response.next_continuation_token == None
+                {
+                    return true;
+                }
+
+                continuation_token = response.next_continuation_token;
+            }
+            Err(error) =>
+            {
+                print_error(&format!("Error: {}", list_objects_error_describe(&error)));
+                return false;
+            }
+        }
+    }
+}
+
+/**
+ * Get the folders (common prefixes) in an s3 bucket under a prefix, returning false if the bucket could not be listed
+ */
+pub fn get_bucket_folders(client: &dyn ObjectStore, bucket_name: &String, prefix: &String, folders: &mut Vec<String>) -> bool
+{
+    let mut continuation_token: Option<String> = None;
+
+    loop
+    {
+        let request = ListObjectsV2Request
+        {
+            bucket: bucket_name.to_owned(),
+            delimiter: Some(String::from("/")),
+            prefix: if prefix.is_empty() { None } else { Some(prefix.to_owned()) },
+            continuation_token: continuation_token.clone(),
+            ..ListObjectsV2Request::default()
+        };
+
+        match client.list_objects_v2(&request)
+        {
+            Ok(response) =>
+            {
+                for common_prefix in response.common_prefixes.unwrap_or(Vec::new())
+                {
+                    if let Some(folder) = common_prefix.prefix
+                    {
+                        folders.push(folder);
+                    }
+                }
+
+                if response.is_truncated != Some(true) || response.next_continuation_token == None
+                {
+                    return true;
+                }
+
+                continuation_token = response.next_continuation_token;
+            }
+            Err(error) =>
+            {
+                print_error(&format!("Error: {}", list_objects_error_describe(&error)));
+                return false;
+            }
+        }
+    }
+}
+
+/**
+ * Struct for checksums
+ */
+#[derive(Clone)]
+pub struct Checksums
+{
+    pub files: BTreeMap<String, String>,
+    pub sizes_mtimes: BTreeMap<String, (u64, u64)>,
+    pub algorithm: HashAlgorithm
+}
+
+/**
+ * Get the path, checksum and recorded size and modification time of a checksums file line, e.g. "my file.png <checksum> 1024 1500000000", or of a GNU md5sum line, e.g. "<checksum>  my file.png"
+ *
+ * The columns are read from the end of the line, as the path can contain spaces while the checksum is a fixed number of hex characters
+ */
+fn parse_checksums_line(line: &str, algorithm: HashAlgorithm) -> Option<(String, String, Option<(u64, u64)>)>
+{
+    let line = line.trim_end_matches('\r');
+    let is_checksum = |checksum: &str| checksum.len() == hash_algorithm_hex_length(algorithm) && checksum.chars().all(|character| character.is_ascii_hexdigit());
+
+    // GNU md5sum lines start with the checksum, then two spaces or a space and an asterisk for a file read in binary mode. Either format is read whatever the checksums_format parameter, so switching it doesn't upload every file again
+    let (escaped, md5sum_line) = if line.starts_with("\\") { (true, &line[1..]) } else { (false, line) };
+    let hex_length = hash_algorithm_hex_length(algorithm);
+
+    if let Some(checksum) = md5sum_line.get(..hex_length).filter(|checksum| is_checksum(checksum))
+    {
+        let path = &md5sum_line[hex_length..];
+
+        if (path.starts_with("  ") || path.starts_with(" *")) && path.len() > 2
+        {
+            let path = if escaped { md5sum_unescape_path(&path[2..]) } else { String::from(&path[2..]) };
+
+            return Some((path, String::from(checksum), None));
+        }
+    }
+
+    let columns: Vec<&str> = line.rsplitn(4, " ").collect();
+    if columns.len() == 4 && !columns[3].is_empty() && is_checksum(columns[2])
+    {
+        if let (Ok(size), Ok(mtime)) = (columns[1].parse::<u64>(), columns[0].parse::<u64>())
+        {
+            return Some((String::from(columns[3]), String::from(columns[2]), Some((size, mtime))));
+        }
+    }
+
+    // Checksums written before sizes and modification times were recorded only have two columns, so their files are always hashed
+    let columns: Vec<&str> = line.rsplitn(2, " ").collect();
+    if columns.len() == 2 && !columns[1].is_empty() && is_checksum(columns[0])
+    {
+        return Some((String::from(columns[1]), String::from(columns[0]), None));
+    }
+
+    return None;
+}
+
+/**
+ * Parse the contents of a checksums file
+ */
+fn parse_checksums(contents: &str) -> Checksums
+{
+    let mut file_checksums = BTreeMap::new();
+    let mut file_sizes_mtimes = BTreeMap::new();
+
+    // Checksums written before the hash algorithm was recorded are all MD5, while the md5sum format has no header and its algorithm is told from the length of the first checksum
+    let mut algorithm = HashAlgorithm::Md5;
+
+    if !contents.split("\n").any(|line| line.starts_with(CHECKSUMS_HASH_HEADER))
+    {
+        if let Some(line) = contents.split("\n").find(|line| !line.trim().is_empty())
+        {
+            if parse_checksums_line(line, HashAlgorithm::Md5).is_none() && parse_checksums_line(line, HashAlgorithm::Sha256).is_some()
+            {
+                algorithm = HashAlgorithm::Sha256;
+            }
+        }
+    }
+
+    for line in contents.split("\n")
+    {
+        if line.starts_with(CHECKSUMS_HASH_HEADER)
+        {
+            match parse_hash_algorithm(line[CHECKSUMS_HASH_HEADER.len()..].trim())
+            {
+                Some(recorded_algorithm) => algorithm = recorded_algorithm,
+
+                // Checksums with an unknown algorithm, e.g. from a newer version, can't be compared against, so every file is treated as new
+                None => return Checksums {files: BTreeMap::new(), sizes_mtimes: BTreeMap::new(), algorithm: algorithm}
+            }
+
+            continue;
+        }
+
+        if let Some((path, checksum, size_mtime)) = parse_checksums_line(line, algorithm)
+        {
+            if let Some(size_mtime) = size_mtime
+            {
+                file_sizes_mtimes.insert(path.to_owned(), size_mtime);
+            }
+
+            file_checksums.insert(path, checksum);
+        }
+    }
+
+    return Checksums {files: file_checksums, sizes_mtimes: file_sizes_mtimes, algorithm: algorithm};
+}
+
+/**
+ * Get the checksums kept in a local file instead of the bucket
+ */
+pub fn local_file_read_checksums(path: &Path) -> Option<Checksums>
+{
+    let mut contents = String::new();
+    File::open(path).and_then(|mut file| file.read_to_string(&mut contents)).ok()?;
+
+    return Some(parse_checksums(&contents));
+}
+
+/**
+ * Get the s3 bucket checksums
+ */
+pub fn get_bucket_object_checksums(client: &dyn ObjectStore, bucket_name: &String, prefix: &String, checksums_name: &String, bucket_objects: &Vec<Object>) -> Option<Checksums>
+{
+    let checksums_key = format!("{}{}", prefix, checksums_name);
+
+    // Objects without a key are skipped rather than unwrapped
+    match bucket_objects.iter().find(|object| object.key.as_ref() == Some(&checksums_key))
+    {
+        Some(_) =>
+        {
+            let request = GetObjectRequest
+            {
+                bucket: bucket_name.to_owned(),
+                key: checksums_key.to_owned(),
+                ..GetObjectRequest::default()
+            };
+
+            match client.get_object(&request)
+            {
+                Ok(response) =>
+                {
+                    // An empty checksums file has no body
+                    let contents_bytes = response.body.unwrap_or(Vec::new());
+
+                    Some(parse_checksums(&String::from_utf8_lossy(&contents_bytes)))
+                }
+                Err(error) =>
+                {
+                    eprintln!("{}: \"{}/{}\" could not be read, {}", stderr_paint(Yellow, "Warning"), bucket_name, checksums_key, get_object_error_describe(&error));
+                    None
+                }
+            }
+        },
+        None =>
+        {
+            None
+        }
+    }
+}
+
+/**
+ * Get checksums from the ETags of the bucket objects under a prefix, which are the MD5 of the contents for objects not uploaded in parts
+ */
+pub fn get_bucket_etag_checksums(bucket_objects: &Vec<Object>, prefix: &String, checksums_name: &String) -> Checksums
+{
+    let checksums_key = format!("{}{}", prefix, checksums_name);
+    let mut file_checksums = BTreeMap::new();
+
+    for object in bucket_objects
+    {
+        if let (&Some(ref key), &Some(ref e_tag)) = (&object.key, &object.e_tag)
+        {
+            let md5 = e_tag.trim_matches('"');
+
+            // The ETag of a multipart upload is a checksum of its part checksums followed by -PARTS, not an MD5 of the contents
+            if key == &checksums_key || !key.starts_with(prefix.as_str()) || md5.contains("-")
+            {
+                continue;
+            }
+
+            file_checksums.insert(String::from(&key[prefix.len()..]), String::from(md5));
+        }
+    }
+
+    return Checksums {files: file_checksums, sizes_mtimes: BTreeMap::new(), algorithm: HashAlgorithm::Md5};
+}
+
+/**
+ * Get the keys of bucket objects that no longer exist as local files, keeping the objects whose paths the filters leave out as their files weren't looked at
+ */
+pub fn get_bucket_stale_keys(bucket_objects: &Vec<Object>, files: &Vec<LocalFile>, local_path: &String, options: &UploadOptions, checksums_name: &String, filters: &PathFilters) -> Vec<String>
+{
+    let local_keys: HashSet<String> = files.iter().map(|file| local_file_get_key(file, local_path, options)).collect();
+    let checksums_key = format!("{}{}", options.prefix, checksums_name);
+    let mut stale_keys: Vec<String> = Vec::new();
+
+    for object in bucket_objects
+    {
+        if let Some(ref key) = object.key
+        {
+            // The key is matched as the path of its file, which is only its relative path when no directories are stripped from it
+            let key_path = if key.starts_with(&options.prefix) { &key[options.prefix.len()..] } else { &key[..] };
+
+            if key != &checksums_key && !local_keys.contains(key) && !path_is_filtered_out(key_path, filters)
+            {
+                stale_keys.push(key.to_owned());
+            }
+        }
+    }
+
+    return stale_keys;
+}
+
+/**
+ * Check if two hex digests are the same, ignoring case as other tools may write the checksums in uppercase
+ */
+fn checksums_match(checksum: &str, other_checksum: &str) -> bool
+{
+    return checksum.to_lowercase() == other_checksum.to_lowercase();
+}
+
+/**
+ * Check if the file matches the checksums
+ */
+fn local_file_matches_checksums(local_path: &String, file: &LocalFile, checksums: &Checksums, strip_components: usize) -> FileStatus
+{
+    for (path, checksum) in &checksums.files
+    {
+        if &local_file_get_key_path(file, local_path, strip_components) == path
+        {
+            if checksums_match(&file.checksum, checksum)
+            {
+                return FileStatus::NotModified;
+            }
+            else
+            {
+                return FileStatus::Modified;
+            }
+        }
+    }
+    return FileStatus::New;
+}
+
+/**
+ * Print the differences between the current and new checksums
+ */
+pub fn print_checksums_diff(current_checksums: &Checksums, new_checksums: &Checksums)
+{
+    let mut paths: Vec<&String> = current_checksums.files.keys().chain(new_checksums.files.keys()).collect();
+    paths.sort();
+    paths.dedup();
+
+    println!("\nManifest changes:\n");
+
+    let mut changes = 0;
+
+    for path in paths
+    {
+        match (current_checksums.files.get(path), new_checksums.files.get(path))
+        {
+            (None, Some(new_checksum)) =>
+            {
+                println!("{}:   {} {}", Green.paint("Added"), path, new_checksum);
+                changes += 1;
+            }
+            (Some(current_checksum), None) =>
+            {
+                println!("{}: {} {}", Red.paint("Removed"), path, current_checksum);
+                changes += 1;
+            }
+            (Some(current_checksum), Some(new_checksum)) =>
+            {
+                if !checksums_match(current_checksum, new_checksum)
+                {
+                    println!("{}: {} {} -> {}", Yellow.paint("Changed"), path, current_checksum, new_checksum);
+                    changes += 1;
+                }
+            }
+            (None, None) => {}
+        }
+    }
+
+    if changes == 0
+    {
+        println!("No changes");
+    }
+}
+
+/**
+ * Get the canned ACL the files are uploaded with, an x-amz-acl header overrides the acl option
+ */
+pub fn upload_options_get_acl(options: &UploadOptions) -> String
+{
+    let mut acl = options.acl.to_owned();
+
+    for &(ref name, ref value) in &options.headers
+    {
+        if name.trim().to_lowercase() == "x-amz-acl"
+        {
+            acl = value.trim().to_owned();
+        }
+    }
+
+    return acl;
+}
+
+/**
+ * Get the first rule whose pattern matches the relative path of a local file
+ */
+pub fn local_file_get_rule<'a>(file: &LocalFile, local_path: &String, rules: &'a Vec<UploadRule>) -> Option<&'a UploadRule>
+{
+    let relative_path = local_file_get_relative_path(file, local_path);
+
+    return rules.iter().find(|rule| path_matches_ignore_patterns(&relative_path, false, &vec![rule.pattern.to_owned()]));
+}
+
+/**
+ * Get the canned ACL a local file is uploaded with, the acl of the first matching rule overrides the one of the options
+ */
+pub fn local_file_get_acl(file: &LocalFile, local_path: &String, options: &UploadOptions) -> String
+{
+    return local_file_get_rule(file, local_path, &options.rules).and_then(|rule| rule.acl.to_owned()).unwrap_or_else(|| upload_options_get_acl(options));
+}
+
+/**
+ * Get the relative paths and ACLs of the new and modified files that match the sensitive patterns and would be uploaded with a public ACL
+ */
+pub fn get_sensitive_files(report: &RunReport, local_path: &String, sensitive_patterns: &Vec<String>, options: &UploadOptions) -> Vec<(String, String)>
+{
+    let mut sensitive_files: Vec<(String, String)> = Vec::new();
+
+    for file in report.new_files.iter().chain(report.modified_files.iter())
+    {
+        let relative_path = local_file_get_relative_path(file, local_path);
+        let acl = local_file_get_acl(file, local_path, options);
+
+        if PUBLIC_ACLS.contains(&acl.as_str()) && path_matches_ignore_patterns(&relative_path, false, sensitive_patterns)
+        {
+            sensitive_files.push((relative_path, acl));
+        }
+    }
+
+    return sensitive_files;
+}
+
+/**
+ * Check if a local file passes the extension allow and deny lists, the deny list wins when an extension is in both
+ */
+pub fn local_file_matches_extensions(file: &LocalFile, only_extensions: &Vec<String>, exclude_extensions: &Vec<String>) -> bool
+{
+    let extension = match file.path.extension().and_then(|extension| extension.to_str())
+    {
+        Some(extension) => extension.to_lowercase(),
+        None            => String::from("")
+    };
+
+    if only_extensions.len() > 0 && !only_extensions.contains(&extension)
+    {
+        return false;
+    }
+
+    return !exclude_extensions.contains(&extension);
+}
+
+/**
+ * Get a list of lowercase extensions from a comma separated parameter value
+ */
+pub fn parse_extensions(value: &str) -> Vec<String>
+{
+    let mut extensions: Vec<String> = Vec::new();
+
+    for extension in value.split(",")
+    {
+        let extension = extension.trim().trim_start_matches('.').to_lowercase();
+
+        if !extension.is_empty()
+        {
+            extensions.push(extension);
+        }
+    }
+
+    return extensions;
+}
+
+/**
+ * Check if a glob pattern matches a path, * and ? don't match across directories while ** matches any number of them
+ */
+fn glob_matches(pattern: &[char], path: &[char]) -> bool
+{
+    if pattern.is_empty()
+    {
+        return path.is_empty();
+    }
+
+    if pattern.starts_with(&['*', '*', '/'])
+    {
+        // **/ matches no directories at all, or everything up to any later slash
+        return glob_matches(&pattern[3..], path) || (0..path.len()).any(|index| path[index] == '/' && glob_matches(&pattern[3..], &path[index + 1..]));
+    }
+
+    if pattern.starts_with(&['*', '*'])
+    {
+        return (0..path.len() + 1).any(|index| glob_matches(&pattern[2..], &path[index..]));
+    }
+
+    match pattern[0]
+    {
+        '*' =>
+        {
+            let segment_length = path.iter().position(|character| *character == '/').unwrap_or(path.len());
+            return (0..segment_length + 1).any(|index| glob_matches(&pattern[1..], &path[index..]));
+        }
+        '?' =>
+        {
+            return !path.is_empty() && path[0] != '/' && glob_matches(&pattern[1..], &path[1..]);
+        }
+        character =>
+        {
+            return !path.is_empty() && path[0] == character && glob_matches(&pattern[1..], &path[1..]);
+        }
+    }
+}
+
+/**
+ * Check if a relative path matches any of the ignore patterns, patterns without a slash also match the file name at any depth
+ */
+fn path_matches_ignore_patterns(relative_path: &String, is_directory: bool, ignore_patterns: &Vec<String>) -> bool
+{
+    let relative_path = relative_path.trim_start_matches('/');
+    let path: Vec<char> = relative_path.chars().collect();
+    let directory_path: Vec<char> = format!("{}/", relative_path).chars().collect();
+    let file_name: Vec<char> = relative_path.rsplit('/').next().unwrap_or("").chars().collect();
+
+    for ignore_pattern in ignore_patterns
+    {
+        // As in .gitignore, a trailing slash only matches directories and a slash anywhere else anchors the pattern to the root
+        let directory_only = ignore_pattern.ends_with("/");
+        let anchored = ignore_pattern.trim_end_matches('/').contains("/");
+        let pattern: Vec<char> = ignore_pattern.trim_start_matches('/').trim_end_matches('/').chars().collect();
+
+        if directory_only && !is_directory
+        {
+            continue;
+        }
+
+        if glob_matches(&pattern, &path) || (is_directory && glob_matches(&pattern, &directory_path)) || (!anchored && glob_matches(&pattern, &file_name))
+        {
+            return true;
+        }
+    }
+
+    return false;
+}
+
+/**
+ * Struct for the filters that leave local files out of a run, from the ignore and include patterns, ignored directories, extension lists and the skip_hidden option
+ */
+pub struct PathFilters
+{
+    pub ignored_directories: Vec<String>,
+    pub ignore_patterns: Vec<String>,
+    pub include_patterns: Vec<String>,
+    pub only_extensions: Vec<String>,
+    pub exclude_extensions: Vec<String>,
+    pub skip_hidden: bool
+}
+
+/**
+ * Check if a path relative to the local path is left out by the filters, as a walk of the tree leaves it out. A path ending with a slash is a directory, which the include patterns and extension lists don't apply to
+ */
+pub fn path_is_filtered_out(relative_path: &str, filters: &PathFilters) -> bool
+{
+    let is_directory = relative_path.ends_with("/");
+    let relative_path = relative_path.trim_end_matches('/');
+    let segments: Vec<&str> = relative_path.split("/").collect();
+    let directory_count = if is_directory { segments.len() } else { segments.len() - 1 };
+
+    if filters.skip_hidden && segments.iter().any(|segment| segment.starts_with("."))
+    {
+        return true;
+    }
+
+    // A directory that is left out leaves out everything inside it
+    for depth in 1..directory_count + 1
+    {
+        if filters.ignored_directories.contains(&segments[depth - 1].to_owned()) || path_matches_ignore_patterns(&segments[..depth].join("/"), true, &filters.ignore_patterns)
+        {
+            return true;
+        }
+    }
+
+    if is_directory
+    {
+        return false;
+    }
+
+    let relative_path = String::from(relative_path);
+
+    if path_matches_ignore_patterns(&relative_path, false, &filters.ignore_patterns)
+    {
+        return true;
+    }
+
+    if !filters.include_patterns.is_empty() && !path_matches_ignore_patterns(&relative_path, false, &filters.include_patterns)
+    {
+        return true;
+    }
+
+    return !local_file_matches_extensions(&LocalFile { path: PathBuf::from(relative_path), checksum: String::from(""), mtime: None }, &filters.only_extensions, &filters.exclude_extensions);
+}
+
+/**
+ * Get the ignore patterns from the .s3ignore file in a local path, which always ignores itself, the config file and the checksums
+ */
+pub fn get_ignore_file_patterns(local_path: &String, checksums_name: &String) -> Vec<String>
+{
+    let mut ignore_patterns: Vec<String> = vec![String::from("/.s3ignore"), format!("/{}", checksums_name), format!("/.{}.tmp", checksums_name), format!("/.{}.journal", checksums_name), String::from("/s3-upload.toml")];
+
+    let mut path = PathBuf::from(local_path);
+    path.push(".s3ignore");
+
+    let mut contents = String::new();
+
+    if let Ok(mut file_handle) = File::open(&path)
+    {
+        if let Err(error) = file_handle.read_to_string(&mut contents)
+        {
+            eprintln!("{}: could not read \"{}\": {}", stderr_paint(Yellow, "Warning"), path.display(), error);
+        }
+    }
+
+    for line in contents.lines()
+    {
+        let line = line.trim();
+
+        if line.is_empty() || line.starts_with("#")
+        {
+            continue;
+        }
+
+        if line.starts_with("!")
+        {
+            eprintln!("{}: negated pattern \"{}\" in .s3ignore is not supported and will be ignored", stderr_paint(Yellow, "Warning"), line);
+            continue;
+        }
+
+        ignore_patterns.push(String::from(line));
+    }
+
+    return ignore_patterns;
+}
+
+/**
+ * Get the local files for a list of paths relative to the root path, e.g. from git diff --name-only, skipping the ones that can't be uploaded with a warning
+ */
+pub fn get_listed_local_files(root_path: &String, paths: &Vec<String>, ignored_directories: &Vec<String>, ignore_patterns: &Vec<String>, include_patterns: &Vec<String>, checksums_name: &String) -> Vec<LocalFile>
+{
+    let mut files: Vec<LocalFile> = Vec::new();
+
+    for path in paths
+    {
+        let relative_path = path.trim().trim_start_matches("./");
+
+        if relative_path.is_empty()
+        {
+            continue;
+        }
+
+        // Only paths inside the root path are uploaded, as their keys are relative to it
+        if Path::new(relative_path).is_absolute() || relative_path.split(|character| character == '/' || character == '\\').any(|segment| segment == "..")
+        {
+            eprintln!("{}: skipping \"{}\", it is not a path inside LOCAL_PATH", stderr_paint(Yellow, "Warning"), relative_path);
+            continue;
+        }
+
+        let path = Path::new(root_path).join(relative_path);
+
+        let file = match fs::metadata(&path)
+        {
+            Ok(ref metadata) if metadata.is_file() => LocalFile { path: path, checksum: String::from(""), mtime: metadata_get_mtime(metadata) },
+            _ =>
+            {
+                eprintln!("{}: skipping \"{}\", it was not found", stderr_paint(Yellow, "Warning"), relative_path);
+                continue;
+            }
+        };
+
+        let relative_path = local_file_get_relative_path(&file, root_path);
+        let directories: Vec<&str> = relative_path.split("/").collect();
+
+        // The listed files are filtered like the ones found by walking the tree, including the directories they are in
+        let directory_ignored = (1..directories.len()).any(|depth| ignored_directories.contains(&directories[depth - 1].to_owned()) || path_matches_ignore_patterns(&directories[..depth].join("/"), true, ignore_patterns));
+
+        if directory_ignored || local_file_is_checksums(&file, checksums_name) || path_matches_ignore_patterns(&relative_path, false, ignore_patterns)
+        {
+            continue;
+        }
+
+        if !include_patterns.is_empty() && !path_matches_ignore_patterns(&relative_path, false, include_patterns)
+        {
+            continue;
+        }
+
+        if !files.iter().any(|listed_file| listed_file.path == file.path)
+        {
+            files.push(file);
+        }
+    }
+
+    return files;
+}
+
+/**
+ * Get files without their checksums from a local path, skipping ignored directories and paths relative to the root path matching the ignore patterns or, when there are include patterns, files not matching any of them, and skipping symlinks unless they are followed
+ */
+pub fn get_local_files(root_path: &String, local_path: &String, files: &mut Vec<LocalFile>, ignored_directories: &mut Vec<String>, ignore_patterns: &Vec<String>, include_patterns: &Vec<String>, follow_symlinks: bool, skip_hidden: bool, visited_directories: &mut Vec<PathBuf>, checksums_name: &String)
+{
+    if let Ok(entries) = fs::read_dir(Path::new(&local_path))
+    {
+        for entry in entries
+        {
+            let file = match entry
+            {
+                Ok(file) => file,
+                Err(error) =>
+                {
+                    eprintln!("{}: skipping an entry of \"{}\": {}", stderr_paint(Yellow, "Warning"), local_path, error);
+                    continue;
+                }
+            };
+
+            let is_symlink = file.file_type().map(|file_type| file_type.is_symlink()).unwrap_or(false);
+
+            if is_symlink && !follow_symlinks
+            {
+                continue;
+            }
+
+            // Hidden files and directories, e.g. .git, .env or .DS_Store, are skipped with everything inside them
+            if skip_hidden && file.file_name().to_str().map(|name| name.starts_with(".")).unwrap_or(false)
+            {
+                continue;
+            }
+
+            // Keys and checksums entries are text, so a file or directory whose path isn't valid UTF-8 is skipped with everything inside it
+            let path = match file.path().to_str()
+            {
+                Some(path) => path.to_owned(),
+                None =>
+                {
+                    eprintln!("{}: skipping \"{}\", its path isn't valid UTF-8", stderr_paint(Yellow, "Warning"), file.path().display());
+                    continue;
+                }
+            };
+
+            // A followed symlink is read as the file or directory it points to, the entry's own metadata is the link's
+            let metadata = if is_symlink { fs::metadata(file.path()) } else { file.metadata() };
+
+            if let Err(ref error) = metadata
+            {
+                eprintln!("{}: skipping \"{}\": {}", stderr_paint(Yellow, "Warning"), file.path().display(), error);
+            }
+
+            if let Ok(metadata) = metadata
+            {
+                let temp_file = LocalFile { path: file.path(), checksum: String::from(""), mtime: metadata_get_mtime(&metadata) };
+
+                if path_matches_ignore_patterns(&local_file_get_relative_path(&temp_file, root_path), metadata.is_dir(), ignore_patterns)
+                {
+                    continue;
+                }
+
+                if metadata.is_dir()
+                {
+                    if !ignored_directories.contains(&file.file_name().to_string_lossy().into_owned())
+                    {
+                        // Symlinked directories are only entered once each, so a link back up the tree can't recurse forever
+                        if follow_symlinks
+                        {
+                            match fs::canonicalize(file.path())
+                            {
+                                Ok(ref canonical_path) if !visited_directories.contains(canonical_path) =>
+                                {
+                                    visited_directories.push(canonical_path.to_owned());
+                                }
+                                _ =>
+                                {
+                                    continue;
+                                }
+                            }
+                        }
+
+                        get_local_files(root_path, &path, files, ignored_directories, ignore_patterns, include_patterns, follow_symlinks, skip_hidden, visited_directories, checksums_name);
+                    }
+                }
+                else if !local_file_is_checksums(&temp_file, checksums_name)
+                {
+                    // Include patterns only apply to files, every directory is still read for files inside it that match
+                    if !include_patterns.is_empty() && !path_matches_ignore_patterns(&local_file_get_relative_path(&temp_file, root_path), false, include_patterns)
+                    {
+                        continue;
+                    }
+
+                    // The checksums are computed afterwards by local_files_compute_hashes, so the files can be hashed in parallel
+                    files.push(temp_file);
+                }
+            }
+        }
+    }
+}
+
+/**
+ * Compute the checksums of local files across worker threads, keeping the files in the order they were given. A file with the same size and modification time as in the bucket checksums keeps its checksum without being read, as long as they were recorded with the same hash algorithm
+ */
+pub fn local_files_compute_hashes(files: Vec<LocalFile>, local_path: &String, checksums: &Checksums, options: &UploadOptions, concurrency: usize) -> (Vec<LocalFile>, Vec<LocalFile>)
+{
+    let files_total = files.len();
+    let queue: Arc<Mutex<VecDeque<(usize, LocalFile)>>> = Arc::new(Mutex::new(files.into_iter().enumerate().collect()));
+    let hashed_files: Arc<Mutex<Vec<Option<Result<LocalFile, LocalFile>>>>> = Arc::new(Mutex::new(vec![None; files_total]));
+    let mut workers = Vec::new();
+
+    for _ in 0..concurrency
+    {
+        let queue = queue.clone();
+        let hashed_files = hashed_files.clone();
+        let local_path = local_path.clone();
+        let checksums = checksums.clone();
+        let options = options.clone();
+
+        workers.push(thread::spawn(move ||
+        {
+            loop
+            {
+                let (index, mut file) = match queue.lock().unwrap().pop_front()
+                {
+                    Some(entry) => entry,
+                    None => break
+                };
+
+                let key_path = local_file_get_key_path(&file, &local_path, options.strip_components);
+
+                // The checksums of compressed files are taken over the compressed contents that are uploaded
+                let checksum = local_file_get_size(&file).and_then(|size|
+                {
+                    let size_mtime = local_file_get_mtime(&file).map(|mtime| (size, mtime));
+
+                    match (checksums.files.get(&key_path), checksums.sizes_mtimes.get(&key_path))
+                    {
+                        (Some(checksum), Some(stored_size_mtime)) if size_mtime.as_ref() == Some(stored_size_mtime) && !local_file_is_gzipped(&file, &options) && checksums.algorithm == options.hash_algorithm => Ok(checksum.to_owned()),
+                        _ if local_file_is_gzipped(&file, &options) => local_file_get_gzip_hash(&file, options.hash_algorithm),
+                        _ => local_file_get_hash(&file, options.hash_algorithm)
+                    }
+                });
+
+                // A file that can't be read, or whose size can't be read, is skipped rather than uploaded with an empty checksum
+                match checksum
+                {
+                    Ok(checksum) =>
+                    {
+                        file.checksum = checksum;
+                        hashed_files.lock().unwrap()[index] = Some(Ok(file));
+                    }
+                    Err(error) =>
+                    {
+                        eprintln!("{}: skipping \"{}\", it could not be read: {}", stderr_paint(Yellow, "Warning"), local_file_get_relative_path(&file, &local_path), error);
+                        hashed_files.lock().unwrap()[index] = Some(Err(file));
+                    }
+                }
+            }
+        }));
+    }
+
+    for worker in workers
+    {
+        worker.join().expect("Hashing worker panicked");
+    }
+
+    let (hashed_files, unreadable_files): (Vec<Result<LocalFile, LocalFile>>, Vec<Result<LocalFile, LocalFile>>) = hashed_files.lock().unwrap().drain(..).map(|file| file.unwrap()).partition(|file| file.is_ok());
+
+    return (hashed_files.into_iter().filter_map(|file| file.ok()).collect(), unreadable_files.into_iter().filter_map(|file| file.err()).collect());
+}
+
+/**
+ * Struct for the files and objects of a run, printed by --format=json
+ */
+pub struct RunReport
+{
+    pub new_files: Vec<LocalFile>,
+    pub modified_files: Vec<LocalFile>,
+    pub skipped_files: Vec<LocalFile>,
+    pub uploaded_files: Vec<LocalFile>,
+    pub failed_files: Vec<LocalFile>,
+    pub uploaded_bytes: u64,
+    pub deleted_keys: Vec<String>,
+    pub removed_paths: Vec<String>,
+    pub invalidation_id: Option<String>,
+    pub errors: Vec<String>
+}
+
+/**
+ * Sort local files into the new, modified and skipped files of a report by comparing them with the bucket checksums
+ */
+pub fn local_files_classify(files: &Vec<LocalFile>, local_path: &String, checksums: &Checksums, checksums_name: &String, options: &UploadOptions, report: &mut RunReport)
+{
+    for file in files
+    {
+        if local_file_is_checksums(file, checksums_name)
+        {
+            continue;
+        }
+
+        let file_status: FileStatus = local_file_matches_checksums(local_path, file, checksums, options.strip_components);
+        if file_status == FileStatus::New
+        {
+            report.new_files.push(file.to_owned());
+        }
+        else if file_status == FileStatus::Modified
+        {
+            report.modified_files.push(file.to_owned());
+        }
+        else
+        {
+            report.skipped_files.push(file.to_owned());
+        }
+    }
+}
+
+/**
+ * Print the counts of uploaded, skipped, failed and deleted files and the bytes uploaded at the end of a run
+ */
+pub fn print_run_summary(report: &RunReport, deleted_count: usize)
+{
+    let failed = format!("{} failed", report.failed_files.len());
+    let failed = if report.failed_files.len() > 0 { stderr_paint(Red, failed) } else { failed };
+
+    eprint!("{}: {}, {} skipped, {}", stderr_paint(Yellow, "Summary"), stderr_paint(Green, format!("{} uploaded ({})", report.uploaded_files.len(), format_size(report.uploaded_bytes))), report.skipped_files.len(), failed);
+
+    if report.deleted_keys.len() > 0
+    {
+        eprint!(", {}", stderr_paint(Red, format!("{} deleted", deleted_count)));
+    }
+
+    eprintln!("");
+}
+
+/**
+ * Get a string escaped and quoted for JSON
+ */
+pub fn json_string(value: &str) -> String
+{
+    let mut escaped = String::from("\"");
+
+    for character in value.chars()
+    {
+        match character
+        {
+            '"'  => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            character if (character as u32) < 0x20 => escaped.push_str(&format!("\\u{:04x}", character as u32)),
+            character => escaped.push(character)
+        }
+    }
+
+    escaped.push('"');
+
+    return escaped;
+}
+
+/**
+ * Get a JSON array of the keys and MD5 checksums of local files
+ */
+fn json_files(files: &Vec<LocalFile>, local_path: &String, options: &UploadOptions) -> String
+{
+    let entries: Vec<String> = files.iter()
+        .map(|file| format!("{{\"key\": {}, \"{}\": {}}}", json_string(&local_file_get_key(file, local_path, options)), hash_algorithm_name(options.hash_algorithm), json_string(&file.checksum)))
+        .collect();
+
+    return format!("[{}]", entries.join(", "));
+}
+
+/**
+ * Print the files and objects of a run as a JSON document
+ */
+pub fn print_json_report(report: &RunReport, local_path: &String, bucket_name: &String, options: &UploadOptions, dry_run: bool)
+{
+    let deleted_keys: Vec<String> = report.deleted_keys.iter().map(|key| json_string(key)).collect();
+    let removed_paths: Vec<String> = report.removed_paths.iter().map(|path| json_string(path)).collect();
+    let errors: Vec<String> = report.errors.iter().map(|error| json_string(error)).collect();
+
+    println!("{{");
+    println!("  \"bucket\": {},", json_string(bucket_name));
+    println!("  \"dry_run\": {},", dry_run);
+    println!("  \"counts\": {{\"new\": {}, \"modified\": {}, \"skipped\": {}, \"uploaded\": {}, \"failed\": {}, \"deleted\": {}, \"uploaded_bytes\": {}}},",
+        report.new_files.len(), report.modified_files.len(), report.skipped_files.len(), report.uploaded_files.len(), report.failed_files.len(), report.deleted_keys.len(), report.uploaded_bytes);
+    println!("  \"new\": {},", json_files(&report.new_files, local_path, options));
+    println!("  \"modified\": {},", json_files(&report.modified_files, local_path, options));
+    println!("  \"skipped\": {},", json_files(&report.skipped_files, local_path, options));
+    println!("  \"uploaded\": {},", json_files(&report.uploaded_files, local_path, options));
+    println!("  \"failed\": {},", json_files(&report.failed_files, local_path, options));
+    println!("  \"deleted\": [{}],", deleted_keys.join(", "));
+    println!("  \"removed\": [{}],", removed_paths.join(", "));
+    println!("  \"invalidation_id\": {},", report.invalidation_id.as_ref().map_or(String::from("null"), |invalidation_id| json_string(invalidation_id)));
+    println!("  \"errors\": [{}]", errors.join(", "));
+    println!("}}");
+}
+
+/**
+ * Print the result of a doctor check
+ */
+fn print_doctor_check(passed: bool, message: &str, hint: &str)
+{
+    if passed
+    {
+        println!("{} {}", Green.paint("PASS"), message);
+    }
+    else
+    {
+        println!("{} {}", Red.paint("FAIL"), message);
+
+        if !hint.is_empty()
+        {
+            println!("     {}", Yellow.paint(hint));
+        }
+    }
+}
+
+/**
+ * Create the HTTP client of the doctor checks, printing a failed check instead when it can't be created, e.g. from an unusable CA bundle
+ */
+fn doctor_http_client(http_options: &HttpOptions) -> Option<hyper::Client>
+{
+    return match create_http_client(http_options)
+    {
+        Ok(client) => Some(client),
+        Err(error) =>
+        {
+            print_doctor_check(false, &format!("the TLS client could not be created: {}", error),
+                "Check the certificates of --ca-bundle can be added to the system TLS library");
+            None
+        }
+    };
+}
+
+/**
+ * Check the credentials, profile, bucket and region configuration without uploading, returning false if a check failed
+ */
+pub fn run_doctor(bucket_name: &String, region: Region, credentials_path: &String, profile: &String, assume_role: &Option<String>, endpoint: &Option<Endpoint>, http_options: &HttpOptions) -> bool
+{
+    println!("\nChecking configuration:\n");
+
+    let provider = get_credentials_provider(credentials_path, profile);
+
+    match provider
+    {
+        CredentialsProvider::Profile(_) =>
+        {
+            if !Path::new(credentials_path).exists()
+            {
+                print_doctor_check(false, &format!("credentials file \"{}\" could not be found and AWS_ACCESS_KEY_ID/AWS_SECRET_ACCESS_KEY are not set", credentials_path),
+                    "Create a credentials file in the directory the app is run from or pass its path with --credentials (see the README for its format), or set the AWS environment variables");
+                return false;
+            }
+
+            print_doctor_check(true, &format!("credentials file \"{}\" found", credentials_path), "");
+
+            if let Err(error) = provider.credentials()
+            {
+                print_doctor_check(false, &format!("profile \"{}\" could not be read from the credentials file: {}", profile, error),
+                    &format!("Add a [{}] section with aws_access_key_id and aws_secret_access_key to the credentials file", profile));
+                return false;
+            }
+
+            print_doctor_check(true, &format!("profile \"{}\" found", profile), "");
+        }
+        CredentialsProvider::Environment(_) if env::var("AWS_SESSION_TOKEN").map(|token| !token.is_empty()).unwrap_or(false) =>
+        {
+            print_doctor_check(true, "temporary credentials found in AWS_ACCESS_KEY_ID/AWS_SECRET_ACCESS_KEY/AWS_SESSION_TOKEN", "");
+        }
+        CredentialsProvider::Environment(_) =>
+        {
+            print_doctor_check(true, "credentials found in AWS_ACCESS_KEY_ID/AWS_SECRET_ACCESS_KEY", "");
+        }
+        CredentialsProvider::AssumedRole(_) =>
+        {
+            // The source credentials are never an assumed role, the role is checked below
+        }
+    }
+
+    // The TLS client is checked before any request, as the bucket clients below are created with the same options
+    let http_client = match doctor_http_client(http_options)
+    {
+        Some(client) => client,
+        None => return false
+    };
+
+    // S3 compatible servers don't provide STS, so with a custom endpoint the credentials are only checked by the bucket requests that follow
+    if endpoint.is_none()
+    {
+        let sts_client = StsClient::new(http_client, provider.clone(), region);
+
+        match sts_client.get_caller_identity(&GetCallerIdentityRequest)
+        {
+            Ok(identity) =>
+            {
+                print_doctor_check(true, &format!("credentials are valid for \"{}\"", identity.arn.unwrap_or(String::from("unknown"))), "");
+            }
+            Err(error) =>
+            {
+                print_doctor_check(false, &format!("credentials were rejected: {}", error),
+                    "Check the aws_access_key_id and aws_secret_access_key are correct and the access key is active");
+                return false;
+            }
+        }
+
+        if let Some(ref role_arn) = *assume_role
+        {
+            let role_provider = get_role_credentials_provider(credentials_path, profile, assume_role, region, http_options);
+            let role_http_client = match doctor_http_client(http_options)
+            {
+                Some(client) => client,
+                None => return false
+            };
+            let role_sts_client = StsClient::new(role_http_client, role_provider, region);
+
+            match role_sts_client.get_caller_identity(&GetCallerIdentityRequest)
+            {
+                Ok(identity) =>
+                {
+                    print_doctor_check(true, &format!("role \"{}\" assumed as \"{}\"", role_arn, identity.arn.unwrap_or(String::from("unknown"))), "");
+                }
+                Err(error) =>
+                {
+                    print_doctor_check(false, &format!("role \"{}\" could not be assumed: {}", role_arn, error),
+                        "Check the role ARN is correct and its trust policy allows the source credentials to assume it with sts:AssumeRole");
+                    return false;
+                }
+            }
+        }
+    }
+
+    // The bucket location can be requested from any region, the default endpoint is used so a bucket in another region is still found
+    let location_client = create_bucket_client(Region::UsEast1, credentials_path, profile, assume_role, endpoint, &None, http_options);
+    let location_request = GetBucketLocationRequest { bucket: bucket_name.to_owned() };
+
+    let bucket_region = match location_client.s3_client.get_bucket_location(&location_request)
+    {
+        Ok(location) =>
+        {
+            match location.location_constraint.as_ref().map(|constraint| constraint.as_str())
+            {
+                None | Some("")  => String::from("us-east-1"),
+                Some("EU")       => String::from("eu-west-1"),
+                Some(constraint) => String::from(constraint)
+            }
+        }
+        Err(error) =>
+        {
+            print_doctor_check(false, &format!("bucket \"{}\" could not be found: {}", bucket_name, error),
+                "Check the bucket name is spelled correctly and the user has s3:GetBucketLocation permission on it");
+            return false;
+        }
+    };
+
+    print_doctor_check(true, &format!("bucket \"{}\" exists", bucket_name), "");
+
+    // S3 compatible servers name their own regions, e.g. nyc3 on DigitalOcean Spaces, which aren't AWS regions the --region parameter accepts
+    if endpoint.is_some()
+    {
+        print_doctor_check(true, &format!("bucket \"{}\" is in region {} of the custom endpoint", bucket_name, bucket_region), "");
+    }
+    else if bucket_region != region.to_string()
+    {
+        print_doctor_check(false, &format!("bucket \"{}\" is in region {}, but the configured region is {}", bucket_name, bucket_region, region),
+            &format!("Run with --region={} to use the bucket's region", bucket_region));
+        return false;
+    }
+    else
+    {
+        print_doctor_check(true, &format!("bucket \"{}\" is in the configured region {}", bucket_name, region), "");
+    }
+
+    let client = create_bucket_client(region, credentials_path, profile, assume_role, endpoint, &None, http_options);
+
+    match client.s3_client.head_bucket(&HeadBucketRequest { bucket: bucket_name.to_owned() })
+    {
+        Ok(_) =>
+        {
+            print_doctor_check(true, &format!("bucket \"{}\" is accessible", bucket_name), "");
+        }
+        Err(error) =>
+        {
+            print_doctor_check(false, &format!("bucket \"{}\" is not accessible: {}", bucket_name, error),
+                "Check the user has s3:ListBucket and s3:PutObject permissions on the bucket");
+            return false;
+        }
+    }
+
+    println!("\n{}", Green.paint("ALL CHECKS PASSED"));
+
+    return true;
+}
+
+/**
+ * Struct for the options of a sync of a local directory to an s3 bucket, the parameters of the command line with the paths and defaults resolved
+ */
+pub struct SyncOptions
+{
+    pub local_path: String,
+    pub bucket_name: String,
+    pub checksums_name: String,
+    pub upload_options: UploadOptions,
+    pub single_file: Option<PathBuf>,
+    pub listed_paths: Option<Vec<String>>,
+    pub ignored_directories: Vec<String>,
+    pub ignore_patterns: Vec<String>,
+    pub include_patterns: Vec<String>,
+    pub only_extensions: Vec<String>,
+    pub exclude_extensions: Vec<String>,
+    pub sensitive_patterns: Vec<String>,
+    pub allow_sensitive: bool,
+    pub follow_symlinks: bool,
+    pub skip_hidden: bool,
+    pub create_dir_placeholders: bool,
+    pub max_file_size: Option<u64>,
+    pub since: Option<Duration>,
+    pub checksums_local_path: Option<PathBuf>,
+    pub memory_budget: u64,
+    pub concurrency: usize,
+    pub only_new: bool,
+    pub only_modified: bool,
+    pub delete: bool,
+    pub prune_checksums: bool,
+    pub delete_orphan_checksums: bool,
+    pub dry_run: bool,
+    pub status: bool,
+    pub output_format: OutputFormat,
+    pub verbose: bool
+}
+
+/**
+ * Trait for the hooks of a sync, so the program running it can confirm the changes before they are made
+ */
+pub trait SyncHooks
+{
+    /**
+     * Confirm the upload of the new and modified files, deletions and folders of a report, returning false to cancel it
+     */
+    fn confirm_upload(&self, report: &RunReport, change_count: usize) -> bool;
+}
+
+/**
+ * Enum for the errors that stop a sync before anything is uploaded
+ */
+pub enum SyncError
+{
+    Usage(String),
+    Failure(String)
+}
+
+impl fmt::Display for SyncError
+{
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result
+    {
+        match *self
+        {
+            SyncError::Usage(ref message) => write!(formatter, "{}", message),
+            SyncError::Failure(ref message) => write!(formatter, "{}", message)
+        }
+    }
+}
+
+/**
+ * Struct for the result of a sync, with the files and keys of the run and whether the upload was confirmed and the checksums updated
+ */
+pub struct SyncResult
+{
+    pub report: RunReport,
+    pub confirmed: bool,
+    pub checksums_uploaded: bool,
+    pub deleted_count: usize,
+    pub changed_keys: Vec<String>
+}
+
+impl SyncResult
+{
+    /**
+     * Check if every upload, deletion and checksums operation of the sync succeeded
+     */
+    pub fn is_success(&self) -> bool
+    {
+        return self.report.failed_files.is_empty() && self.report.errors.is_empty();
+    }
+}
+
+/**
+ * Get the local files of a sync from the single file, the listed paths or the walked tree, with the files its options leave out removed, and the empty directories when placeholders are created
+ */
+fn sync_get_local_files(options: &SyncOptions, filters: &PathFilters) -> Result<(Vec<LocalFile>, Vec<LocalFile>, Vec<PathBuf>), SyncError>
+{
+    let local_path = &options.local_path;
+    let checksums_name = &options.checksums_name;
+
+    let mut ignored_directories = filters.ignored_directories.clone();
+    let ignore_patterns = &filters.ignore_patterns;
+
+    let mut files: Vec<LocalFile> = Vec::new();
+    let mut visited_directories: Vec<PathBuf> = vec![PathBuf::from(local_path)];
+
+    match (&options.single_file, &options.listed_paths)
+    {
+        (&Some(ref path), _) =>
+        {
+            files.push(LocalFile { path: path.to_owned(), checksum: String::from(""), mtime: fs::metadata(path).ok().and_then(|metadata| metadata_get_mtime(&metadata)) });
+        }
+        (&None, &Some(ref paths)) =>
+        {
+            files = get_listed_local_files(local_path, paths, &ignored_directories, ignore_patterns, &filters.include_patterns, checksums_name);
+        }
+        (&None, &None) =>
+        {
+            get_local_files(local_path, local_path, &mut files, &mut ignored_directories, ignore_patterns, &filters.include_patterns, options.follow_symlinks, filters.skip_hidden, &mut visited_directories, checksums_name);
+        }
+    }
+
+    // Empty directories are otherwise left out of the bucket, as S3 only stores objects. A directory only counts as empty if the walk found no files in it
+    let mut empty_directories: Vec<PathBuf> = Vec::new();
+
+    if options.create_dir_placeholders
+    {
+        get_empty_local_directories(local_path, Path::new(local_path), &files, &ignored_directories, ignore_patterns, filters.skip_hidden, &mut empty_directories);
+    }
+
+    files.retain(|file| local_file_matches_extensions(file, &filters.only_extensions, &filters.exclude_extensions));
+
+    // Leave out the files modified before the since option without hashing them, a file without a modification time is compared as usual
+    if let Some(since) = options.since
+    {
+        let since_epoch = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or(Duration::from_secs(0)).checked_sub(since).unwrap_or(Duration::from_secs(0));
+        let cutoff = since_epoch.as_secs() * 1000000000 + since_epoch.subsec_nanos() as u64;
+        let files_count = files.len();
+
+        files.retain(|file| file.mtime.map_or(true, |mtime| mtime >= cutoff));
+
+        if options.verbose
+        {
+            eprintln!("Left out {} file(s) modified before --since", files_count - files.len());
+        }
+    }
+
+    // Two files that are only told apart by the stripped directories can't both be uploaded
+    let files = local_files_strip_components(files, local_path, options.upload_options.strip_components)
+        .map_err(|error| SyncError::Usage(format!("--strip-components={} gives the same key to two files, {}", options.upload_options.strip_components, error)))?;
+
+    // Skip the files larger than the max_file_size option, leaving them out of the checksums so they are looked at again on the next run. A file whose size can't be read is kept, so it is skipped with a warning when it is hashed
+    let (files, oversized_files): (Vec<LocalFile>, Vec<LocalFile>) = files.into_iter().partition(|file| options.max_file_size.map_or(true, |max_file_size| local_file_get_size(file).map_or(true, |size| size <= max_file_size)));
+
+    for file in &oversized_files
+    {
+        eprintln!("{}: skipping \"{}\" ({}), it is larger than --max-file-size", stderr_paint(Yellow, "Warning"), local_file_get_relative_path(file, local_path), format_size(local_file_get_size(file).unwrap_or(0)));
+    }
+
+    return Ok((files, oversized_files, empty_directories));
+}
+
+/**
+ * Rewrite the checksums without the entries of files that no longer exist locally, or whose objects are no longer in the bucket with the delete_orphan_checksums option, instead of uploading
+ */
+fn sync_prune_checksums(client: &Arc<dyn ObjectStore>, options: &SyncOptions, filters: &PathFilters, checksums: &Checksums, files: &Vec<LocalFile>, bucket_objects: &Vec<Object>, result: &mut SyncResult)
+{
+    let local_path = &options.local_path;
+    let bucket_name = &options.bucket_name;
+    let upload_options = &options.upload_options;
+    let text_output = options.output_format == OutputFormat::Text || options.output_format == OutputFormat::Github;
+    let progress_output = options.output_format != OutputFormat::Quiet;
+
+    let (pruned_checksums, pruned_paths) = if options.delete_orphan_checksums
+    {
+        checksums_prune_orphans(checksums, bucket_objects, &upload_options.prefix)
+    }
+    else
+    {
+        checksums_prune(checksums, files, local_path, upload_options, filters)
+    };
+    let stale_name = if options.delete_orphan_checksums { "orphan" } else { "stale" };
+
+    if text_output
+    {
+        println!("");
+
+        for path in &pruned_paths
+        {
+            println!("{}: {}", Red.paint("Pruned"), path);
+        }
+    }
+
+    if !pruned_paths.is_empty() && !options.dry_run
+    {
+        let (checksums_directory, checksums_file_name) = sync_get_checksums_location(options);
+
+        match local_file_write_checksums(&pruned_checksums, &checksums_directory, &checksums_file_name, upload_options.checksums_format)
+        {
+            Ok(checksums_file) if options.checksums_local_path.is_some() =>
+            {
+                result.checksums_uploaded = true;
+
+                if progress_output
+                {
+                    eprintln!("\nSaved the checksums to \"{}\"", checksums_file.path.display());
+                }
+            }
+            Ok(checksums_file) =>
+            {
+                let checksums_options = checksums_upload_options(upload_options);
+                let upload_result = local_file_upload_to_bucket(&**client, &checksums_file, local_path, bucket_name, &checksums_options);
+
+                if options.output_format == OutputFormat::Github
+                {
+                    print_github_upload_result(&checksums_file, &local_file_get_key(&checksums_file, local_path, &checksums_options), bucket_name, &upload_result);
+                }
+                else if progress_output
+                {
+                    eprintln!("");
+                    print_upload_result(&checksums_file, &local_file_get_key(&checksums_file, local_path, &checksums_options), bucket_name, &upload_result, "");
+                }
+
+                match upload_result
+                {
+                    Ok(_) =>
+                    {
+                        result.checksums_uploaded = true;
+
+                        if let Err(error) = local_file_delete_checksums(local_path, &options.checksums_name)
+                        {
+                            result.report.errors.push(format!("could not remove the local checksums: {}", error));
+                        }
+                    }
+                    Err(error) =>
+                    {
+                        result.report.errors.push(format!("could not upload the checksums: {}", error));
+                    }
+                }
+            }
+            Err(error) =>
+            {
+                result.report.errors.push(format!("could not create the local checksums: {}", error));
+            }
+        }
+    }
+
+    if text_output
+    {
+        for error in &result.report.errors
+        {
+            print_error(&format!("Error: {}", error));
+        }
+
+        if pruned_paths.is_empty()
+        {
+            eprintln!("{}", stderr_paint(Green, format!("No {} checksums entries", stale_name)));
+        }
+        else if options.dry_run
+        {
+            eprintln!("\n{}", stderr_paint(Yellow, format!("DRY RUN - {} {} checksums entries would be pruned", pruned_paths.len(), stale_name)));
+        }
+        else if result.report.errors.is_empty()
+        {
+            eprintln!("\n{}", stderr_paint(Green, format!("PRUNED {} {} checksums entries", pruned_paths.len(), stale_name)));
+        }
+    }
+
+    result.report.removed_paths = pruned_paths;
+}
+
+/**
+ * Get the directory and file name the checksums of a sync are written to, the local path and checksums name unless they are only kept locally
+ */
+fn sync_get_checksums_location(options: &SyncOptions) -> (String, String)
+{
+    return match options.checksums_local_path
+    {
+        Some(ref path) => (path.parent().map_or(String::from("."), |directory| directory.to_string_lossy().into_owned()), path.file_name().map_or(options.checksums_name.to_owned(), |name| name.to_string_lossy().into_owned())),
+        None => (options.local_path.to_owned(), options.checksums_name.to_owned())
+    };
+}
+
+/**
+ * Upload the new and modified files of a local directory to an s3 bucket once the hooks confirm them, deleting the stale keys if the delete option is set and updating the checksums, or only list them with the dry_run and status options
+ */
+pub fn sync(client: &Arc<dyn ObjectStore>, options: &SyncOptions, hooks: &dyn SyncHooks) -> Result<SyncResult, SyncError>
+{
+    let local_path = &options.local_path;
+    let bucket_name = &options.bucket_name;
+    let checksums_name = &options.checksums_name;
+    let upload_options = &options.upload_options;
+
+    // The files of a single file, listed or recently modified run are only part of the tree, so the checksums entries and objects of the others are kept
+    let partial_file_list = options.single_file.is_some() || options.listed_paths.is_some() || options.since.is_some();
+
+    if partial_file_list && (options.delete || options.prune_checksums)
+    {
+        return Err(SyncError::Usage(String::from("--delete and --prune-checksums can't be used with a single file, --from-stdin or --since")));
+    }
+
+    let mut result = SyncResult
+    {
+        report: RunReport
+        {
+            new_files: Vec::new(),
+            modified_files: Vec::new(),
+            skipped_files: Vec::new(),
+            uploaded_files: Vec::new(),
+            failed_files: Vec::new(),
+            uploaded_bytes: 0,
+            deleted_keys: Vec::new(),
+            removed_paths: Vec::new(),
+            invalidation_id: None,
+            errors: Vec::new()
+        },
+        confirmed: false,
+        checksums_uploaded: false,
+        deleted_count: 0,
+        changed_keys: Vec::new()
+    };
+
+    let mut ignore_patterns = get_ignore_file_patterns(local_path, checksums_name);
+    ignore_patterns.extend(options.ignore_patterns.iter().cloned());
+
+    let filters = PathFilters
+    {
+        ignored_directories: options.ignored_directories.clone(),
+        ignore_patterns: ignore_patterns,
+        include_patterns: options.include_patterns.clone(),
+        only_extensions: options.only_extensions.clone(),
+        exclude_extensions: options.exclude_extensions.clone(),
+        skip_hidden: options.skip_hidden
+    };
+
+    let (files, oversized_files, empty_directories) = sync_get_local_files(options, &filters)?;
+
+    let mut bucket_objects: Vec<Object> = Vec::new();
+
+    // Without the bucket objects every file would look new and the bucket checksums would be overwritten, so nothing is hashed, uploaded or deleted
+    if !get_bucket_objects(&**client, bucket_name, &upload_options.prefix, &mut bucket_objects)
+    {
+        return Err(SyncError::Failure(format!("Could not list the objects in \"{}\"", bucket_name)));
+    }
+
+    // GitHub Actions annotations only replace the upload results, the rest of the output is the text one
+    let text_output = options.output_format == OutputFormat::Text || options.output_format == OutputFormat::Github;
+
+    // The progress and results of a run are printed on stderr, so they are kept with --format=json as the JSON is the only thing on stdout
+    let progress_output = options.output_format != OutputFormat::Quiet;
+
+    // The checksums only kept locally are read from their file, they are never looked for in the bucket
+    let checksums_location = match options.checksums_local_path
+    {
+        Some(ref path) => path.display().to_string(),
+        None => format!("{}/{}{}", bucket_name, upload_options.prefix, checksums_name)
+    };
+
+    let bucket_checksums = match options.checksums_local_path
+    {
+        Some(ref path) => local_file_read_checksums(path),
+        None => get_bucket_object_checksums(&**client, bucket_name, &upload_options.prefix, checksums_name, &bucket_objects)
+    };
+
+    let checksums_found = bucket_checksums.is_some();
+
+    let checksums = match bucket_checksums
+    {
+        Some(checksums) =>
+        {
+            if options.verbose
+            {
+                eprintln!("Loaded \"{}\" with {} file checksum(s)", checksums_location, checksums.files.len());
+            }
+
+            checksums
+        },
+        None =>
+        {
+            if options.verbose
+            {
+                eprintln!("No \"{}\" found, using the ETags of the bucket objects", checksums_location);
+            }
+
+            // Without checksums, objects already in the bucket are compared by ETag so an existing bucket isn't uploaded again
+            get_bucket_etag_checksums(&bucket_objects, &upload_options.prefix, checksums_name)
+        }
+    };
+
+    if options.prune_checksums || options.delete_orphan_checksums
+    {
+        if !checksums_found
+        {
+            return Err(SyncError::Failure(format!("No \"{}\" could be found to prune", checksums_location)));
+        }
+
+        sync_prune_checksums(client, options, &filters, &checksums, &files, &bucket_objects, &mut result);
+
+        return Ok(result);
+    }
+
+    // The files uploaded by a run that was interrupted before its checksums were uploaded are in the journal, so they are compared against the checksums they were uploaded with
+    let journal_path = local_journal_get_path(local_path, checksums_name);
+
+    let checksums = match local_journal_read(&journal_path, bucket_name, &upload_options.prefix)
+    {
+        Some(ref journal_checksums) if journal_checksums.algorithm == checksums.algorithm && !journal_checksums.files.is_empty() =>
+        {
+            if text_output
+            {
+                eprintln!("Resuming an interrupted run, {} file(s) were already uploaded according to \"{}\"", journal_checksums.files.len(), journal_path.display());
+            }
+
+            checksums_merge(&checksums, journal_checksums)
+        }
+        _ => checksums
+    };
+
+    // Checksums recorded with another hash algorithm can't be compared, so every file is hashed and uploaded again
+    if checksums.algorithm != upload_options.hash_algorithm && !checksums.files.is_empty() && text_output
+    {
+        eprintln!("{}", stderr_paint(Yellow, format!("The bucket checksums use {} instead of {}, so every file is uploaded again", hash_algorithm_name(checksums.algorithm), hash_algorithm_name(upload_options.hash_algorithm))));
+    }
+
+    let (files, unreadable_files) = local_files_compute_hashes(files, local_path, &checksums, upload_options, options.concurrency);
+
+    local_files_classify(&files, local_path, &checksums, checksums_name, upload_options, &mut result.report);
+
+    // Hold back the modified files if the only_new option is set, or the new files if the only_modified option is set
+    let held_files: Vec<LocalFile> = if options.only_new { result.report.modified_files.drain(..).collect() } else if options.only_modified { result.report.new_files.drain(..).collect() } else { Vec::new() };
+    let held_status = if options.only_new { "modified, --only-new" } else { "new, --only-modified" };
+
+    // The held files keep the entries they had in the checksums, so they aren't taken as uploaded
+    let checksummed_files: Vec<LocalFile> = files.iter().filter(|file| !held_files.iter().any(|held_file| held_file.path == file.path)).cloned().collect();
+
+    // A skipped oversized or unreadable file still exists locally, so its entry and object are kept
+    let mut local_files = files.clone();
+    local_files.extend(oversized_files.iter().cloned());
+    local_files.extend(unreadable_files.iter().cloned());
+
+    // Find the files in the checksums that no longer exist locally, a partial file list leaves the other entries as they are
+    if !partial_file_list
+    {
+        let (_, mut removed_paths) = checksums_prune(&checksums, &local_files, local_path, upload_options, &filters);
+        removed_paths.sort();
+
+        result.report.removed_paths = removed_paths;
+    }
+
+    // Find the bucket objects that no longer exist locally if the delete option is set, the objects of the paths the filters leave out are kept
+    if options.delete
+    {
+        result.report.deleted_keys = get_bucket_stale_keys(&bucket_objects, &local_files, local_path, upload_options, checksums_name, &filters);
+    }
+
+    // The placeholders of the directories that are still empty are kept, and only the missing ones are created
+    let placeholder_keys: Vec<String> = empty_directories.iter().filter_map(|directory| local_directory_get_placeholder_key(directory, local_path, upload_options)).collect();
+    result.report.deleted_keys.retain(|key| !placeholder_keys.contains(key));
+
+    let new_placeholder_keys: Vec<String> = placeholder_keys.into_iter().filter(|key| !bucket_objects.iter().any(|object| object.key.as_ref() == Some(key))).collect();
+
+    let pending_upload = result.report.modified_files.len() > 0 || result.report.new_files.len() > 0 || result.report.deleted_keys.len() > 0 || new_placeholder_keys.len() > 0;
+
+    // List the unchanged files with the checksums they matched if the verbose option is set
+    if options.verbose && text_output && result.report.skipped_files.len() > 0
+    {
+        println!("\nFiles skipped as not modified:\n");
+
+        for file in &result.report.skipped_files
+        {
+            println!("{}:  {} ({})", Yellow.paint("Skipped"), local_file_get_relative_path(file, local_path), file.checksum);
+        }
+    }
+
+    // Without the delete option, the files deleted locally are still listed so the changes since the last upload are complete
+    let removed_paths: &[String] = if options.delete { &[] } else { &result.report.removed_paths };
+
+    if (pending_upload || !removed_paths.is_empty() || !held_files.is_empty()) && text_output
+    {
+        println!("\n{}:\n", if pending_upload { "Files found to be uploaded" } else { "Files found to be deleted locally" });
+
+        for file in &result.report.new_files
+        {
+            println!("{}:      {}", Green.paint("New"), local_file_get_relative_path(file, local_path));
+        }
+
+        for file in &result.report.modified_files
+        {
+            println!("{}: {}", Green.paint("Modified"), local_file_get_relative_path(file, local_path));
+        }
+
+        for key in &new_placeholder_keys
+        {
+            println!("{}:   {}", Green.paint("Folder"), key);
+        }
+
+        for file in &held_files
+        {
+            println!("{}:  {} ({})", Yellow.paint("Skipped"), local_file_get_relative_path(file, local_path), held_status);
+        }
+
+        for key in &result.report.deleted_keys
+        {
+            println!("{}:  {}", Red.paint("Deleted"), key);
+        }
+
+        for path in removed_paths
+        {
+            println!("{}:  {}", Red.paint("Deleted"), path);
+        }
+    }
+
+    // The held files are counted as skipped once the unchanged files have been listed
+    result.report.skipped_files.extend(held_files.iter().cloned());
+
+    // Files that usually hold secrets are only uploaded with a public ACL, from the acl option or a rule, if the allow_sensitive option is set
+    let sensitive_files = get_sensitive_files(&result.report, local_path, &options.sensitive_patterns, upload_options);
+
+    if !sensitive_files.is_empty()
+    {
+        print_error(&format!("\nWARNING: {} file(s) that may hold secrets would be uploaded with a public ACL:", sensitive_files.len()));
+
+        for &(ref path, ref acl) in &sensitive_files
+        {
+            print_error(&format!("Sensitive: {} ({})", path, acl));
+        }
+
+        if !options.status && !options.dry_run && !options.allow_sensitive
+        {
+            return Err(SyncError::Usage(String::from("\nRefusing to upload them, run with --i-know to upload them anyway or add them to .s3ignore")));
+        }
+    }
+
+    if options.status
+    {
+        if text_output && !pending_upload
+        {
+            eprintln!("\n{}", stderr_paint(Yellow, "No pending modified/new files"));
+        }
+    }
+    else if options.dry_run
+    {
+        if text_output
+        {
+            if !pending_upload
+            {
+                eprintln!("\n{}", stderr_paint(Yellow, "No pending modified/new files"));
+            }
+
+            let new_checksums = checksums_keep_filtered_out(&checksums, &checksums_keep_held(&checksums, &local_file_get_checksums(&checksummed_files, local_path, upload_options), &held_files, local_path, upload_options), &filters);
+
+            if partial_file_list && checksums_found
+            {
+                print_checksums_diff(&checksums, &checksums_merge(&checksums, &new_checksums));
+            }
+            else
+            {
+                print_checksums_diff(&checksums, &new_checksums);
+            }
+
+            eprintln!("\n{}", stderr_paint(Yellow, "DRY RUN - no files uploaded"));
+        }
+    }
+    else if pending_upload
+    {
+        // Deletions count towards the changes to confirm too, so a large deletion still has to be confirmed
+        let change_count = result.report.new_files.len() + result.report.modified_files.len() + result.report.deleted_keys.len() + new_placeholder_keys.len();
+
+        if !hooks.confirm_upload(&result.report, change_count)
+        {
+            eprintln!("\n{}", stderr_paint(Yellow, "UPLOAD CANCELLED"));
+
+            return Ok(result);
+        }
+
+        result.confirmed = true;
+
+        if progress_output
+        {
+            eprintln!("");
+        }
+
+        let memory_budget = Arc::new(MemoryBudget { limit: options.memory_budget, in_use: Mutex::new(0), released: Condvar::new() });
+
+        let mut upload_files = result.report.modified_files.clone();
+        upload_files.extend(result.report.new_files.iter().cloned());
+
+        let (failed_files, uploaded_bytes) = local_files_upload_to_bucket(client, upload_files.clone(), local_path, bucket_name, upload_options, &memory_budget, options.concurrency, options.output_format, &Some(journal_path.to_owned()));
+        result.report.uploaded_files = upload_files.into_iter().filter(|file| !failed_files.iter().any(|failed_file| failed_file.path == file.path)).collect();
+        result.report.failed_files = failed_files;
+        result.report.uploaded_bytes = uploaded_bytes;
+
+        result.changed_keys = result.report.uploaded_files.iter().map(|file| local_file_get_key(file, local_path, upload_options)).collect();
+
+        for key in &new_placeholder_keys
+        {
+            let placeholder_result = bucket_put_directory_placeholder(&**client, bucket_name, key, upload_options);
+
+            if progress_output
+            {
+                print_directory_placeholder_result(bucket_name, key, &placeholder_result);
+            }
+
+            if let Err(error) = placeholder_result
+            {
+                result.report.errors.push(format!("could not create the folder \"{}\": {}", key, error));
+            }
+        }
+
+        for (key, delete_result) in delete_bucket_objects(&**client, bucket_name, &result.report.deleted_keys)
+        {
+            if progress_output
+            {
+                print_delete_result(bucket_name, &key, &delete_result);
+            }
+
+            match delete_result
+            {
+                Ok(_) =>
+                {
+                    result.deleted_count += 1;
+                    result.changed_keys.push(key.to_owned());
+                }
+                Err(error) =>
+                {
+                    result.report.errors.push(format!("could not delete \"{}\": {}", key, error));
+                }
+            }
+        }
+
+        // Failed files are left out of the checksums so they are uploaded again on the next run
+        let checksummed_files: Vec<LocalFile> = checksummed_files.into_iter().filter(|file| !result.report.failed_files.iter().any(|failed_file| failed_file.path == file.path)).collect();
+        let (checksums_directory, checksums_file_name) = sync_get_checksums_location(options);
+
+        // A partial file list keeps the checksums of the other files, as they weren't looked at, and a filtered one keeps the entries of the paths it leaves out
+        let new_checksums = if partial_file_list && checksums_found
+        {
+            local_file_write_checksums(&checksums_merge(&checksums, &local_file_get_checksums(&checksummed_files, local_path, upload_options)), &checksums_directory, &checksums_file_name, upload_options.checksums_format)
+        }
+        else
+        {
+            local_file_write_checksums(&checksums_keep_filtered_out(&checksums, &checksums_keep_held(&checksums, &local_file_get_checksums(&checksummed_files, local_path, upload_options), &held_files, local_path, upload_options), &filters), &checksums_directory, &checksums_file_name, upload_options.checksums_format)
+        };
+
+        match new_checksums
+        {
+            Ok(new_checksums) if options.checksums_local_path.is_some() =>
+            {
+                result.checksums_uploaded = true;
+
+                if progress_output
+                {
+                    eprintln!("Saved the checksums to \"{}\"", new_checksums.path.display());
+                }
+
+                if let Err(error) = local_journal_delete(&journal_path)
+                {
+                    let error = format!("could not remove the local journal: {}", error);
+
+                    if text_output
+                    {
+                        print_error(&format!("Error: {}", error));
+                    }
+
+                    result.report.errors.push(error);
+                }
+            }
+            Ok(new_checksums) =>
+            {
+                let checksums_options = checksums_upload_options(upload_options);
+
+                let upload_result = local_file_upload_to_bucket(&**client, &new_checksums, local_path, bucket_name, &checksums_options);
+
+                if options.output_format == OutputFormat::Github
+                {
+                    print_github_upload_result(&new_checksums, &local_file_get_key(&new_checksums, local_path, &checksums_options), bucket_name, &upload_result);
+                }
+                else if progress_output
+                {
+                    print_upload_result(&new_checksums, &local_file_get_key(&new_checksums, local_path, &checksums_options), bucket_name, &upload_result, "");
+                }
+
+                match upload_result
+                {
+                    Ok(_) =>
+                    {
+                        result.checksums_uploaded = true;
+
+                        // The local checksums and journal are only removed once the checksums are confirmed to be in the bucket
+                        if let Err(error) = local_file_delete_checksums(local_path, checksums_name).and_then(|_| local_journal_delete(&journal_path))
+                        {
+                            let error = format!("could not remove the local checksums: {}", error);
+
+                            if text_output
+                            {
+                                print_error(&format!("Error: {}", error));
+                            }
+
+                            result.report.errors.push(error);
+                        }
+                    }
+                    Err(error) =>
+                    {
+                        // The bucket keeps its old checksums, so the next run compares against them along with the journal of the files uploaded since then
+                        if text_output
+                        {
+                            print_error(&format!("Warning: the bucket checksums are now out of date. The new checksums were kept in \"{}\", and the next run skips the files already uploaded using \"{}\"", new_checksums.path.display(), journal_path.display()));
+                        }
+
+                        result.report.errors.push(format!("could not upload the checksums: {}", error));
+                    }
+                }
+            }
+            Err(error) =>
+            {
+                let error = format!("could not create the local checksums: {}", error);
+
+                if text_output
+                {
+                    print_error(&format!("Error: {}", error));
+                }
+
+                result.report.errors.push(error);
+            }
+        }
+    }
+    else if text_output
+    {
+        eprintln!("\n{}", stderr_paint(Yellow, "No pending modified/new files"));
+    }
+
+    return Ok(result);
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    /**
+     * Get a local file for a path, without reading it
+     */
+    fn test_file(path: &str) -> LocalFile
+    {
+        return LocalFile { path: PathBuf::from(path), checksum: String::from(""), mtime: None };
+    }
+
+    /**
+     * Get the upload options of a plain upload, as with no parameters set
+     */
+    fn test_upload_options() -> UploadOptions
+    {
+        return UploadOptions
+        {
+            prefix: String::from(""),
+            acl: String::from("private"),
+            cache_control: None,
+            gzip: false,
+            storage_class: None,
+            server_side_encryption: None,
+            ssekms_key_id: None,
+            headers: Vec::new(),
+            mime_overrides: HashMap::new(),
+            sniff_mime: false,
+            hash_algorithm: HashAlgorithm::Md5,
+            checksums_format: ChecksumsFormat::Path,
+            multipart_threshold: MULTIPART_THRESHOLD,
+            max_retries: 0,
+            checksums_tagging: None,
+            strip_components: 0,
+            rules: Vec::new()
+        };
+    }
+
+    /**
+     * Object store keeping the objects of a single bucket in memory, by key
+     */
+    struct MemoryStore
+    {
+        objects: Mutex<BTreeMap<String, Vec<u8>>>
+    }
+
+    impl MemoryStore
+    {
+        fn new() -> MemoryStore
+        {
+            return MemoryStore { objects: Mutex::new(BTreeMap::new()) };
+        }
+
+        /**
+         * Get the body of an object, if it is in the store
+         */
+        fn get(&self, key: &str) -> Option<Vec<u8>>
+        {
+            return self.objects.lock().unwrap().get(key).cloned();
+        }
+    }
+
+    impl ObjectStore for MemoryStore
+    {
+        fn head_bucket(&self, _: &HeadBucketRequest) -> Result<(), HeadBucketError>
+        {
+            return Ok(());
+        }
+
+        fn list_objects_v2(&self, request: &ListObjectsV2Request) -> Result<ListObjectsV2Output, ListObjectsV2Error>
+        {
+            let prefix = request.prefix.to_owned().unwrap_or(String::from(""));
+
+            // The ETag of an object uploaded in one part is the MD5 of its body, as on S3
+            let contents: Vec<Object> = self.objects.lock().unwrap().iter()
+                .filter(|&(key, _)| key.starts_with(prefix.as_str()))
+                .map(|(key, body)|
+                {
+                    let mut digest = Md5::new();
+                    digest.input(body);
+
+                    Object { key: Some(key.to_owned()), e_tag: Some(format!("\"{}\"", digest.result_str())), size: Some(body.len() as i32), ..Object::default() }
+                })
+                .collect();
+
+            return Ok(ListObjectsV2Output { key_count: Some(contents.len() as i32), contents: Some(contents), is_truncated: Some(false), ..ListObjectsV2Output::default() });
+        }
+
+        fn get_object(&self, request: &GetObjectRequest) -> Result<GetObjectOutput, GetObjectError>
+        {
+            return match self.get(&request.key)
+            {
+                Some(body) => Ok(GetObjectOutput { body: Some(body), ..GetObjectOutput::default() }),
+                None => Err(GetObjectError::NoSuchKey(request.key.to_owned()))
+            };
+        }
+
+        fn put_object(&self, request: &PutObjectRequest) -> Result<PutObjectOutput, PutObjectError>
+        {
+            self.objects.lock().unwrap().insert(request.key.to_owned(), request.body.to_owned().unwrap_or(Vec::new()));
+
+            return Ok(PutObjectOutput::default());
+        }
+
+        fn create_multipart_upload(&self, _: &CreateMultipartUploadRequest) -> Result<CreateMultipartUploadOutput, CreateMultipartUploadError>
+        {
+            return Err(CreateMultipartUploadError::Unknown(String::from("multipart uploads aren't supported by the memory store")));
+        }
+
+        fn upload_part(&self, _: &UploadPartRequest) -> Result<UploadPartOutput, UploadPartError>
+        {
+            return Err(UploadPartError::Unknown(String::from("multipart uploads aren't supported by the memory store")));
+        }
+
+        fn complete_multipart_upload(&self, _: &CompleteMultipartUploadRequest) -> Result<CompleteMultipartUploadOutput, CompleteMultipartUploadError>
+        {
+            return Err(CompleteMultipartUploadError::Unknown(String::from("multipart uploads aren't supported by the memory store")));
+        }
+
+        fn abort_multipart_upload(&self, _: &AbortMultipartUploadRequest) -> Result<AbortMultipartUploadOutput, AbortMultipartUploadError>
+        {
+            return Ok(AbortMultipartUploadOutput::default());
+        }
+
+        fn delete_objects(&self, request: &DeleteObjectsRequest) -> Result<DeleteObjectsOutput, DeleteObjectsError>
+        {
+            let mut objects = self.objects.lock().unwrap();
+
+            for object in &request.delete.objects
+            {
+                objects.remove(&object.key);
+            }
+
+            return Ok(DeleteObjectsOutput::default());
+        }
+
+        fn set_object_headers(&self, _: &String, _: &String, _: &Option<HashMap<String, String>>)
+        {
+        }
+    }
+
+    /**
+     * Get the sync options of a plain sync of a local path, as with no parameters set and --yes --quiet
+     */
+    fn test_sync_options(local_path: &String, delete: bool) -> SyncOptions
+    {
+        return SyncOptions
+        {
+            local_path: local_path.to_owned(),
+            bucket_name: String::from("bucket"),
+            checksums_name: String::from("checksums.txt"),
+            upload_options: test_upload_options(),
+            single_file: None,
+            listed_paths: None,
+            ignored_directories: Vec::new(),
+            ignore_patterns: Vec::new(),
+            include_patterns: Vec::new(),
+            only_extensions: Vec::new(),
+            exclude_extensions: Vec::new(),
+            sensitive_patterns: Vec::new(),
+            allow_sensitive: false,
+            follow_symlinks: false,
+            skip_hidden: false,
+            create_dir_placeholders: false,
+            max_file_size: None,
+            since: None,
+            checksums_local_path: None,
+            memory_budget: 16 * 1024 * 1024,
+            concurrency: 2,
+            only_new: false,
+            only_modified: false,
+            delete: delete,
+            prune_checksums: false,
+            delete_orphan_checksums: false,
+            dry_run: false,
+            status: false,
+            output_format: OutputFormat::Quiet,
+            verbose: false
+        };
+    }
+
+    /**
+     * Sync hooks confirming every upload, as with --yes
+     */
+    struct TestHooks;
+
+    impl SyncHooks for TestHooks
+    {
+        fn confirm_upload(&self, _: &RunReport, _: usize) -> bool
+        {
+            return true;
+        }
+    }
+
+    /**
+     * Run a sync with the test hooks, failing the test if it is stopped
+     */
+    fn test_sync(client: &Arc<dyn ObjectStore>, options: &SyncOptions) -> SyncResult
+    {
+        return sync(client, options, &TestHooks).unwrap_or_else(|error| panic!("the sync was stopped: {}", error));
+    }
+
+    /**
+     * Get the sorted relative paths of local files
+     */
+    fn test_relative_paths(files: &Vec<LocalFile>, local_path: &String) -> Vec<String>
+    {
+        let mut paths: Vec<String> = files.iter().map(|file| local_file_get_relative_path(file, local_path)).collect();
+        paths.sort();
+
+        return paths;
+    }
+
+    /**
+     * Get a path in the temporary directory that is unique to this test run
+     */
+    fn test_temp_path(name: &str) -> PathBuf
+    {
+        return env::temp_dir().join(format!("s3-file-upload-test-{}-{}", std::process::id(), name));
+    }
+
+    #[test]
+    fn extension_in_both_lists_is_excluded()
+    {
+        let only_extensions = parse_extensions("html,.CSS");
+        let exclude_extensions = parse_extensions("css");
+
+        assert!(local_file_matches_extensions(&test_file("site/index.html"), &only_extensions, &exclude_extensions));
+        assert!(!local_file_matches_extensions(&test_file("site/style.css"), &only_extensions, &exclude_extensions));
+        assert!(!local_file_matches_extensions(&test_file("site/app.js"), &only_extensions, &exclude_extensions));
+    }
+
+    #[test]
+    fn chunked_hash_matches_one_shot_digest()
+    {
+        // A body over two chunks long, so the last chunk is a partial one
+        let body: Vec<u8> = (0..HASH_CHUNK_SIZE * 2 + 1234).map(|index| (index % 251) as u8).collect();
+        let path = test_temp_path("chunked-hash.bin");
+        fs::write(&path, &body).unwrap();
+
+        for &algorithm in &[HashAlgorithm::Md5, HashAlgorithm::Sha256]
+        {
+            let mut digest = hash_algorithm_digest(algorithm);
+            digest.input(&body);
+
+            assert_eq!(local_file_get_hash(&LocalFile { path: path.to_owned(), checksum: String::from(""), mtime: None }, algorithm).unwrap(), digest.result_str());
+        }
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn extensionless_file_is_octet_stream()
+    {
+        assert_eq!(local_file_get_mime(&test_file("site/LICENSE"), &HashMap::new(), false), "application/octet-stream");
+        assert_eq!(local_file_get_mime(&test_file("site/docker/Dockerfile"), &HashMap::new(), false), "application/octet-stream");
+    }
+
+    #[test]
+    fn extension_case_is_ignored_for_mime_types()
+    {
+        assert_eq!(local_file_get_mime(&test_file("site/app.JS"), &HashMap::new(), false), "application/javascript");
+        assert_eq!(local_file_get_mime(&test_file("site/app.js"), &HashMap::new(), false), "application/javascript");
+    }
+
+    #[test]
+    fn leftover_checksums_file_is_never_listed()
+    {
+        let root = test_temp_path("leftover-checksums");
+        fs::create_dir_all(root.join("sub")).unwrap();
+        fs::write(root.join("index.html"), "<html></html>").unwrap();
+        fs::write(root.join("checksums.txt"), "index.html d41d8cd98f00b204e9800998ecf8427e\n").unwrap();
+        fs::write(root.join("sub").join("checksums.txt"), "").unwrap();
+
+        let root_path = root.to_str().unwrap().to_owned();
+        let mut files: Vec<LocalFile> = Vec::new();
+        get_local_files(&root_path, &root_path, &mut files, &mut Vec::new(), &Vec::new(), &Vec::new(), false, false, &mut Vec::new(), &String::from("checksums.txt"));
+
+        let paths: Vec<String> = files.iter().map(|file| local_file_get_relative_path(file, &root_path)).collect();
+        assert_eq!(paths, vec![String::from("index.html")]);
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn local_path_repeated_in_the_tree_is_only_removed_once()
+    {
+        assert_eq!(local_file_get_relative_path(&test_file("data/data/file.txt"), &String::from("data")), "data/file.txt");
+        assert_eq!(local_file_get_relative_path(&test_file("/srv/data/data/file.txt"), &String::from("/srv/data")), "data/file.txt");
+        assert_eq!(local_file_get_relative_path(&test_file("data/my data.txt"), &String::from("data")), "my data.txt");
+    }
+
+    #[test]
+    fn trailing_slash_on_the_local_path_gives_the_same_keys()
+    {
+        let root = test_temp_path("trailing-slash");
+        fs::create_dir_all(root.join("css")).unwrap();
+        fs::write(root.join("index.html"), "<html></html>").unwrap();
+        fs::write(root.join("css").join("site.css"), "body {}").unwrap();
+
+        let get_keys = |local_path: &str|
+        {
+            let local_path = local_path_canonicalize(local_path).unwrap();
+            let mut files: Vec<LocalFile> = Vec::new();
+            get_local_files(&local_path, &local_path, &mut files, &mut Vec::new(), &Vec::new(), &Vec::new(), false, false, &mut Vec::new(), &String::from("checksums.txt"));
+
+            let mut keys: Vec<String> = files.iter().map(|file| local_file_get_key_path(file, &local_path, 0)).collect();
+            keys.sort();
+            keys
+        };
+
+        let root_path = root.to_str().unwrap().to_owned();
+        let keys = get_keys(&root_path);
+
+        assert_eq!(keys, vec![String::from("css/site.css"), String::from("index.html")]);
+        assert_eq!(get_keys(&format!("{}/", root_path)), keys);
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn checksums_are_written_sorted_and_stable()
+    {
+        let root = test_temp_path("sorted-checksums");
+        fs::create_dir_all(root.join("sub")).unwrap();
+
+        let root_path = root.to_str().unwrap().to_owned();
+        let mut files: Vec<LocalFile> = Vec::new();
+
+        for &(path, checksum) in &[("b.txt", "92eb5ffee6ae2fec3ad71c777531578f"), ("sub/c.txt", "4a8a08f09d37b73795649038408b5f33"), ("a.txt", "0cc175b9c0f1b6a831c399e269772661")]
+        {
+            fs::write(root.join(path), path).unwrap();
+            files.push(LocalFile { path: root.join(path), checksum: String::from(checksum), mtime: Some(1500000000000000000) });
+        }
+
+        let checksums_name = String::from("checksums.txt");
+        let first_path = local_file_create_checksums(&files, &root_path, &checksums_name, &test_upload_options()).unwrap().path;
+        let first_contents = fs::read(&first_path).unwrap();
+
+        files.reverse();
+        let second_path = local_file_create_checksums(&files, &root_path, &checksums_name, &test_upload_options()).unwrap().path;
+
+        assert_eq!(fs::read(&second_path).unwrap(), first_contents);
+        assert!(!root.join(".checksums.txt.tmp").exists());
+
+        let paths: Vec<String> = String::from_utf8(first_contents).unwrap().lines().skip(1).map(|line| line.split(" ").next().unwrap().to_owned()).collect();
+        assert_eq!(paths, vec![String::from("a.txt"), String::from("b.txt"), String::from("sub/c.txt")]);
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn downloaded_gzipped_file_matches_its_compressed_checksum()
+    {
+        let path = test_temp_path("downloaded.html");
+        fs::write(&path, "<html><body>compressed on upload</body></html>").unwrap();
+
+        let file = test_file(path.to_str().unwrap());
+        let gzip_checksum = local_file_get_gzip_hash(&file, HashAlgorithm::Md5).unwrap();
+
+        assert!(local_file_matches_downloaded_checksum(&file, &gzip_checksum, HashAlgorithm::Md5));
+        assert!(local_file_matches_downloaded_checksum(&file, &local_file_get_hash(&file, HashAlgorithm::Md5).unwrap(), HashAlgorithm::Md5));
+        assert!(!local_file_matches_downloaded_checksum(&file, &String::from("d41d8cd98f00b204e9800998ecf8427e"), HashAlgorithm::Md5));
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn sync_uploads_every_file_to_an_empty_bucket()
+    {
+        let root = test_temp_path("sync-empty-bucket");
+        fs::create_dir_all(root.join("css")).unwrap();
+        fs::write(root.join("index.html"), "<html></html>").unwrap();
+        fs::write(root.join("css").join("site.css"), "body {}").unwrap();
+
+        let local_path = root.to_str().unwrap().to_owned();
+        let store = Arc::new(MemoryStore::new());
+        let client: Arc<dyn ObjectStore> = store.clone();
+
+        let result = test_sync(&client, &test_sync_options(&local_path, false));
+
+        assert!(result.is_success());
+        assert!(result.checksums_uploaded);
+        assert_eq!(test_relative_paths(&result.report.new_files, &local_path), vec![String::from("css/site.css"), String::from("index.html")]);
+        assert_eq!(test_relative_paths(&result.report.uploaded_files, &local_path), vec![String::from("css/site.css"), String::from("index.html")]);
+        assert!(result.report.modified_files.is_empty() && result.report.skipped_files.is_empty());
+
+        assert_eq!(store.get("index.html"), Some(b"<html></html>".to_vec()));
+        assert_eq!(store.get("css/site.css"), Some(b"body {}".to_vec()));
+        assert!(store.get("checksums.txt").is_some());
+        assert!(!root.join("checksums.txt").exists());
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn sync_classifies_new_modified_unchanged_and_deleted_files()
+    {
+        let root = test_temp_path("sync-classify");
+        fs::create_dir_all(&root).unwrap();
+        fs::write(root.join("unchanged.txt"), "same").unwrap();
+        fs::write(root.join("modified.txt"), "before").unwrap();
+        fs::write(root.join("deleted.txt"), "gone soon").unwrap();
+
+        let local_path = root.to_str().unwrap().to_owned();
+        let store = Arc::new(MemoryStore::new());
+        let client: Arc<dyn ObjectStore> = store.clone();
+
+        assert!(test_sync(&client, &test_sync_options(&local_path, true)).is_success());
+
+        fs::write(root.join("modified.txt"), "after the change").unwrap();
+        fs::remove_file(root.join("deleted.txt")).unwrap();
+        fs::write(root.join("new.txt"), "new").unwrap();
+
+        let result = test_sync(&client, &test_sync_options(&local_path, true));
+
+        assert!(result.is_success());
+        assert_eq!(test_relative_paths(&result.report.new_files, &local_path), vec![String::from("new.txt")]);
+        assert_eq!(test_relative_paths(&result.report.modified_files, &local_path), vec![String::from("modified.txt")]);
+        assert_eq!(test_relative_paths(&result.report.skipped_files, &local_path), vec![String::from("unchanged.txt")]);
+        assert_eq!(result.report.deleted_keys, vec![String::from("deleted.txt")]);
+        assert_eq!(result.report.removed_paths, vec![String::from("deleted.txt")]);
+
+        assert_eq!(store.get("modified.txt"), Some(b"after the change".to_vec()));
+        assert_eq!(store.get("new.txt"), Some(b"new".to_vec()));
+        assert_eq!(store.get("deleted.txt"), None);
+
+        // A third sync finds nothing left to upload
+        let result = test_sync(&client, &test_sync_options(&local_path, true));
+
+        assert!(result.report.new_files.is_empty() && result.report.modified_files.is_empty() && result.report.deleted_keys.is_empty());
+        assert_eq!(result.report.skipped_files.len(), 3);
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn sync_dry_run_uploads_nothing()
+    {
+        let root = test_temp_path("sync-dry-run");
+        fs::create_dir_all(&root).unwrap();
+        fs::write(root.join("index.html"), "<html></html>").unwrap();
+
+        let local_path = root.to_str().unwrap().to_owned();
+        let store = Arc::new(MemoryStore::new());
+        let client: Arc<dyn ObjectStore> = store.clone();
+
+        let mut options = test_sync_options(&local_path, false);
+        options.dry_run = true;
+
+        let result = test_sync(&client, &options);
+
+        assert!(result.is_success());
+        assert!(!result.checksums_uploaded);
+        assert_eq!(test_relative_paths(&result.report.new_files, &local_path), vec![String::from("index.html")]);
+        assert!(result.report.uploaded_files.is_empty());
+        assert!(store.objects.lock().unwrap().is_empty());
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn sync_compares_against_etags_without_a_checksums_file()
+    {
+        let root = test_temp_path("sync-etags");
+        fs::create_dir_all(&root).unwrap();
+        fs::write(root.join("same.txt"), "same").unwrap();
+        fs::write(root.join("changed.txt"), "changed locally").unwrap();
+
+        let local_path = root.to_str().unwrap().to_owned();
+        let store = Arc::new(MemoryStore::new());
+        store.objects.lock().unwrap().insert(String::from("same.txt"), b"same".to_vec());
+        store.objects.lock().unwrap().insert(String::from("changed.txt"), b"in the bucket".to_vec());
+        let client: Arc<dyn ObjectStore> = store.clone();
+
+        let result = test_sync(&client, &test_sync_options(&local_path, false));
+
+        assert!(result.is_success());
+        assert_eq!(test_relative_paths(&result.report.skipped_files, &local_path), vec![String::from("same.txt")]);
+        assert_eq!(test_relative_paths(&result.report.modified_files, &local_path), vec![String::from("changed.txt")]);
+        assert_eq!(store.get("changed.txt"), Some(b"changed locally".to_vec()));
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn sync_refuses_public_sensitive_files()
+    {
+        let root = test_temp_path("sync-sensitive");
+        fs::create_dir_all(&root).unwrap();
+        fs::write(root.join("index.html"), "<html></html>").unwrap();
+        fs::write(root.join(".env"), "SECRET=1").unwrap();
+
+        let local_path = root.to_str().unwrap().to_owned();
+        let store = Arc::new(MemoryStore::new());
+        let client: Arc<dyn ObjectStore> = store.clone();
+
+        let mut options = test_sync_options(&local_path, false);
+        options.upload_options.acl = String::from("public-read");
+        options.sensitive_patterns = vec![String::from(".env")];
+
+        match sync(&client, &options, &TestHooks)
+        {
+            Err(SyncError::Usage(_)) => {}
+            _ => panic!("the sensitive file was not refused")
+        }
+
+        assert!(store.objects.lock().unwrap().is_empty());
+
+        // They are uploaded once it is confirmed they are meant to be public
+        options.allow_sensitive = true;
+
+        assert!(test_sync(&client, &options).is_success());
+        assert_eq!(store.get(".env"), Some(b"SECRET=1".to_vec()));
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn unreadable_file_is_skipped_and_empty_file_is_kept()
+    {
+        let root = test_temp_path("unreadable-file");
+        fs::create_dir_all(root.join("directory.txt")).unwrap();
+        fs::write(root.join("readable.txt"), "readable").unwrap();
+        fs::write(root.join("empty.txt"), "").unwrap();
+        fs::write(root.join("deleted.txt"), "deleted").unwrap();
+
+        let local_path = root.to_str().unwrap().to_owned();
+        let files: Vec<LocalFile> = ["readable.txt", "empty.txt", "directory.txt", "deleted.txt"].iter().map(|name| test_file(root.join(name).to_str().unwrap())).collect();
+        let checksums = Checksums { files: BTreeMap::new(), sizes_mtimes: BTreeMap::new(), algorithm: HashAlgorithm::Md5 };
+
+        // Neither a directory nor a file deleted after it was listed can be read, even by root, which permissions don't apply to
+        fs::remove_file(root.join("deleted.txt")).unwrap();
+
+        let (hashed_files, unreadable_files) = local_files_compute_hashes(files, &local_path, &checksums, &test_upload_options(), 2);
+
+        assert_eq!(test_relative_paths(&hashed_files, &local_path), vec![String::from("empty.txt"), String::from("readable.txt")]);
+        assert_eq!(test_relative_paths(&unreadable_files, &local_path), vec![String::from("deleted.txt"), String::from("directory.txt")]);
+        assert!(hashed_files.iter().any(|file| file.checksum == "d41d8cd98f00b204e9800998ecf8427e"));
+        assert!(local_file_get_size(&test_file(root.join("deleted.txt").to_str().unwrap())).is_err());
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn checksums_match_ignoring_case()
+    {
+        assert!(checksums_match("d41d8cd98f00b204e9800998ecf8427e", "D41D8CD98F00B204E9800998ECF8427E"));
+        assert!(checksums_match("D41d8Cd98f00B204e9800998ECF8427e", "d41d8cd98f00b204e9800998ecf8427e"));
+        assert!(!checksums_match("d41d8cd98f00b204e9800998ecf8427e", "D41D8CD98F00B204E9800998ECF8427F"));
+
+        // A checksums file written by another tool with uppercase digests still marks the file as unchanged
+        let checksums = parse_checksums("index.html D41D8CD98F00B204E9800998ECF8427E\n");
+        let mut file = test_file("site/index.html");
+        file.checksum = String::from("d41d8cd98f00b204e9800998ecf8427e");
+
+        assert!(local_file_matches_checksums(&String::from("site"), &file, &checksums, 0) == FileStatus::NotModified);
+    }
+
+    #[test]
+    fn path_with_spaces_round_trips_through_the_checksums_file()
+    {
+        let root = test_temp_path("spaced-checksums");
+        fs::create_dir_all(root.join("my photos")).unwrap();
+
+        let local_path = root.to_str().unwrap().to_owned();
+        let mut files: Vec<LocalFile> = Vec::new();
+
+        for &(path, checksum) in &[("my file.png", "0cc175b9c0f1b6a831c399e269772661"), ("my photos/a b  c.jpg", "92eb5ffee6ae2fec3ad71c777531578f")]
+        {
+            fs::write(root.join(path), path).unwrap();
+            files.push(LocalFile { path: root.join(path), checksum: String::from(checksum), mtime: Some(1500000000000000000) });
+        }
+
+        let checksums_file = local_file_create_checksums(&files, &local_path, &String::from("checksums.txt"), &test_upload_options()).unwrap();
+        let checksums = local_file_read_checksums(&checksums_file.path).unwrap();
+
+        assert_eq!(checksums.files.get("my file.png"), Some(&String::from("0cc175b9c0f1b6a831c399e269772661")));
+        assert_eq!(checksums.files.get("my photos/a b  c.jpg"), Some(&String::from("92eb5ffee6ae2fec3ad71c777531578f")));
+        assert_eq!(checksums.sizes_mtimes.get("my file.png"), Some(&(11, 1500000000000000000)));
+
+        for file in &files
+        {
+            assert!(local_file_matches_checksums(&local_path, file, &checksums, 0) == FileStatus::NotModified);
+        }
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn windows_drive_letters_and_verbatim_prefixes_are_removed()
+    {
+        assert_eq!(path_normalize_separators("C:\\site\\index.html"), "/site/index.html");
+        assert_eq!(path_normalize_separators("d:\\site\\css\\site.css"), "/site/css/site.css");
+        assert_eq!(path_normalize_separators("\\\\?\\C:\\site\\index.html"), "/site/index.html");
+        assert_eq!(path_normalize_separators("site/index.html"), "site/index.html");
+
+        // The paths are read as text, as Windows paths aren't split into components on other platforms
+
+        assert_eq!(local_file_get_relative_path(&test_file("C:\\site\\css\\site.css"), &String::from("C:\\site")), "css/site.css");
+        assert_eq!(local_file_get_relative_path(&test_file("C:\\site\\index.html"), &String::from("C:\\site\\")), "index.html");
+        assert_eq!(local_file_get_relative_path(&test_file("\\\\?\\C:\\site\\index.html"), &String::from("C:\\site")), "index.html");
+        assert_eq!(local_file_get_relative_path(&test_file("\\\\?\\C:\\site\\css\\site.css"), &String::from("\\\\?\\C:\\site")), "css/site.css");
+
+        // The local path is still removed by whole components, so C:\site doesn't match C:\sites
+        assert_eq!(local_file_get_relative_path(&test_file("C:\\sites\\index.html"), &String::from("C:\\site")), "sites/index.html");
+    }
+
+    #[test]
+    fn md5sum_format_round_trips_without_a_header()
+    {
+        let root = test_temp_path("md5sum-format");
+        fs::create_dir_all(&root).unwrap();
+
+        let local_path = root.to_str().unwrap().to_owned();
+        let mut checksums = Checksums { files: BTreeMap::new(), sizes_mtimes: BTreeMap::new(), algorithm: HashAlgorithm::Sha256 };
+        checksums.files.insert(String::from("my file.txt"), String::from("2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824"));
+        checksums.files.insert(String::from("line\nbreak.txt"), String::from("486ea46224d1bb4fb680f34f7c9ad96a8f24ec88be73ea8e5a6c65260e9cb8a7"));
+        checksums.sizes_mtimes.insert(String::from("my file.txt"), (5, 1500000000000000000));
+
+        let checksums_file = local_file_write_checksums(&checksums, &local_path, &String::from("checksums.txt"), ChecksumsFormat::Md5sum).unwrap();
+        let contents = fs::read_to_string(&checksums_file.path).unwrap();
+
+        assert_eq!(contents, "\\486ea46224d1bb4fb680f34f7c9ad96a8f24ec88be73ea8e5a6c65260e9cb8a7  line\\nbreak.txt\n2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824  my file.txt\n");
+
+        let parsed_checksums = parse_checksums(&contents);
+
+        assert!(parsed_checksums.algorithm == HashAlgorithm::Sha256);
+        assert_eq!(parsed_checksums.files, checksums.files);
+        assert!(parsed_checksums.sizes_mtimes.is_empty());
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn non_utf8_names_are_skipped_by_the_walk()
+    {
+        use std::ffi::OsStr;
+        use std::os::unix::ffi::OsStrExt;
+
+        let root = test_temp_path("non-utf8-names");
+        fs::create_dir_all(root.join(OsStr::from_bytes(b"dir\xff"))).unwrap();
+        fs::write(root.join("index.html"), "<html></html>").unwrap();
+        fs::write(root.join(OsStr::from_bytes(b"bad\xff.txt")), "bad").unwrap();
+        fs::write(root.join(OsStr::from_bytes(b"dir\xff")).join("inside.txt"), "inside").unwrap();
+
+        let local_path = root.to_str().unwrap().to_owned();
+        let mut files: Vec<LocalFile> = Vec::new();
+        get_local_files(&local_path, &local_path, &mut files, &mut Vec::new(), &Vec::new(), &Vec::new(), false, false, &mut Vec::new(), &String::from("checksums.txt"));
+
+        assert_eq!(test_relative_paths(&files, &local_path), vec![String::from("index.html")]);
+
+        // A path that isn't valid UTF-8 still gets a relative path, with the invalid bytes replaced
+        assert_eq!(local_file_get_relative_path(&LocalFile { path: root.join(OsStr::from_bytes(b"bad\xff.txt")), checksum: String::from(""), mtime: None }, &local_path), "bad\u{FFFD}.txt");
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn tagging_is_only_added_to_multipart_uploads()
+    {
+        let mut request = PutObjectRequest::default();
+        put_object_request_set_header(&mut request, "x-amz-meta-team", "web");
+        put_object_request_set_header(&mut request, "x-amz-tagging", "env=prod");
+
+        let headers = put_object_request_get_unsent_headers(&request, true);
+
+        assert_eq!(headers.get("x-amz-meta-team"), Some(&String::from("web")));
+        assert_eq!(headers.get("x-amz-tagging"), Some(&String::from("env=prod")));
+
+        // A single request upload sends its tagging itself
+        let headers = put_object_request_get_unsent_headers(&request, false);
+
+        assert_eq!(headers.get("x-amz-meta-team"), Some(&String::from("web")));
+        assert_eq!(headers.get("x-amz-tagging"), None);
+    }
+
+    #[test]
+    fn sync_delete_keeps_the_objects_the_filters_leave_out()
+    {
+        let root = test_temp_path("sync-delete-filters");
+        fs::create_dir_all(root.join("drafts")).unwrap();
+        fs::write(root.join("index.html"), "<html></html>").unwrap();
+        fs::write(root.join("removed.html"), "<html></html>").unwrap();
+        fs::write(root.join("style.css"), "body {}").unwrap();
+        fs::write(root.join(".htaccess"), "Deny from all").unwrap();
+        fs::write(root.join("drafts").join("draft.html"), "<html></html>").unwrap();
+
+        let local_path = root.to_str().unwrap().to_owned();
+        let store = Arc::new(MemoryStore::new());
+        let client: Arc<dyn ObjectStore> = store.clone();
+
+        assert!(test_sync(&client, &test_sync_options(&local_path, true)).is_success());
+
+        fs::remove_file(root.join("removed.html")).unwrap();
+
+        let mut options = test_sync_options(&local_path, true);
+        options.include_patterns = vec![String::from("*.html")];
+        options.ignore_patterns = vec![String::from("drafts/")];
+        options.skip_hidden = true;
+
+        let result = test_sync(&client, &options);
+
+        assert!(result.is_success());
+        assert_eq!(result.report.deleted_keys, vec![String::from("removed.html")]);
+        assert_eq!(store.get("removed.html"), None);
+        assert!(store.get("style.css").is_some() && store.get(".htaccess").is_some() && store.get("drafts/draft.html").is_some());
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn filtered_sync_and_prune_keep_the_checksums_entries_the_filters_leave_out()
+    {
+        let root = test_temp_path("sync-prune-filters");
+        fs::create_dir_all(&root).unwrap();
+        fs::write(root.join("index.html"), "<html></html>").unwrap();
+        fs::write(root.join("removed.html"), "<html></html>").unwrap();
+        fs::write(root.join("style.css"), "body {}").unwrap();
+
+        let local_path = root.to_str().unwrap().to_owned();
+        let store = Arc::new(MemoryStore::new());
+        let client: Arc<dyn ObjectStore> = store.clone();
+
+        assert!(test_sync(&client, &test_sync_options(&local_path, false)).is_success());
+
+        fs::remove_file(root.join("removed.html")).unwrap();
+        fs::write(root.join("index.html"), "<html>changed</html>").unwrap();
+
+        let mut options = test_sync_options(&local_path, false);
+        options.include_patterns = vec![String::from("*.html")];
+
+        let result = test_sync(&client, &options);
+
+        assert_eq!(result.report.removed_paths, vec![String::from("removed.html")]);
+
+        let checksums = parse_checksums(&String::from_utf8(store.get("checksums.txt").unwrap()).unwrap());
+        assert_eq!(checksums.files.keys().cloned().collect::<Vec<String>>(), vec![String::from("index.html"), String::from("style.css")]);
+
+        // Pruning with the filters doesn't remove the entries of the files they leave out either
+        fs::remove_file(root.join("style.css")).unwrap();
+
+        options.prune_checksums = true;
+
+        let result = test_sync(&client, &options);
+
+        assert!(result.is_success());
+        assert!(result.report.removed_paths.is_empty());
+
+        let checksums = parse_checksums(&String::from_utf8(store.get("checksums.txt").unwrap()).unwrap());
+        assert_eq!(checksums.files.len(), 2);
+
+        options.include_patterns = Vec::new();
+
+        assert_eq!(test_sync(&client, &options).report.removed_paths, vec![String::from("style.css")]);
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
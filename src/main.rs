@@ -3,472 +3,1696 @@
  */
 
 extern crate rusoto;
-extern crate crypto;
+extern crate toml;
 extern crate term_painter;
+extern crate s3_file_upload;
 
 use std::env;
-use std::fs;
+use std::str::FromStr;
 use std::path::Path;
 use std::path::PathBuf;
-use std::fs::{File, remove_file};
-use std::io::{stdin, Read, Write};
-use std::clone::Clone;
+use std::fs::File;
+use std::io::{stdin, BufRead, Read};
 use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::process;
+use std::time::{Duration, Instant};
 
-use rusoto::{ProfileProvider, Region};
-use rusoto::s3::{Object, S3Client, ListObjectsV2Request, GetObjectRequest, PutObjectRequest};
-use rusoto::default_tls_client;
-
-use crypto::md5::Md5;
-use crypto::digest::Digest;
+use rusoto::Region;
+use rusoto::s3::PutObjectRequest;
 
 use term_painter::ToStyle;
 use term_painter::Color::*;
 
+use s3_file_upload::*;
+
 /**
- * Enum for file status
+ * Exit code of a run where an upload, deletion or checksums operation failed
  */
-#[derive(PartialEq)]
-enum FileStatus
-{
-    NotModified,
-    Modified,
-    New
-}
+const EXIT_FAILURE: i32 = 1;
 
 /**
- * Struct for files
+ * Exit code of a run stopped by a usage error, such as an invalid parameter or missing credentials
  */
-#[derive(Clone)]
-struct LocalFile
-{
-    path: PathBuf,
-    md5: String
-}
+const EXIT_USAGE: i32 = 2;
 
 /**
- * Get the mime type for a local file
+ * Get the bucket and the parameters set in a TOML config file, which are read before the command line parameters so the command line overrides them
  */
-fn local_file_get_mime(file: &LocalFile) -> String
+fn get_config_parameters(config_path: &Path) -> Result<(Option<String>, Vec<String>), String>
 {
-    let extension = file.path.extension().unwrap().to_str();
-
-    let mime_type = match extension.as_ref()
-    {
-        Some(&"html") => "text/html",
-        Some(&"css")  => "text/css",
-        Some(&"png")  => "image/png",
-        Some(&"gif")  => "image/gif",
-        Some(&"jpg")  => "image/jpeg",
-        Some(&"xml")  => "application/xml",
-        Some(&"txt")  => "text/plain",
-        _             => "application/octet-stream",
-    };
+    let mut contents = String::new();
+    File::open(config_path).and_then(|mut file| file.read_to_string(&mut contents)).map_err(|error| error.to_string())?;
 
-    return mime_type.to_owned();
-}
+    let config = contents.parse::<toml::Value>().map_err(|error| error.to_string())?;
+    let config = config.as_table().ok_or(String::from("the config must be a table"))?;
 
-/**
- * Get the MD5 checksum of a local file
- */
-fn local_file_get_md5(file: &LocalFile) -> String
-{
-    let mut file_handle = File::open(&file.path).unwrap();
-    let mut contents: Vec<u8> = Vec::new();
+    let mut bucket_name: Option<String> = None;
+    let mut parameters: Vec<String> = Vec::new();
 
-    match file_handle.read_to_end(&mut contents)
+    for (name, value) in config
     {
-        Ok(_) =>
-        {
-            let mut md5_checksum = Md5::new();
-            md5_checksum.input(&contents);
-            return md5_checksum.result_str();
-        }
-        Err(error) =>
+        match (name.as_str(), value)
         {
-            println!("{}: {}", Red.paint("Error"), error);
-            return String::from("");
+            ("bucket", &toml::Value::String(ref value)) =>
+            {
+                bucket_name = Some(value.to_owned());
+            }
+            ("region", &toml::Value::String(ref value)) |
+            ("profile", &toml::Value::String(ref value)) |
+            ("credentials", &toml::Value::String(ref value)) |
+            ("prefix", &toml::Value::String(ref value)) |
+            ("acl", &toml::Value::String(ref value)) =>
+            {
+                parameters.push(format!("--{}={}", name, value));
+            }
+            ("ignore", &toml::Value::String(ref value)) =>
+            {
+                parameters.push(format!("--ignore={}", value));
+            }
+            ("ignore", &toml::Value::Array(ref values)) =>
+            {
+                let mut patterns: Vec<&str> = Vec::new();
+
+                for value in values
+                {
+                    patterns.push(value.as_str().ok_or(String::from("ignore must be a list of strings"))?);
+                }
+
+                parameters.push(format!("--ignore={}", patterns.join(",")));
+            }
+            ("mime_overrides", &toml::Value::Table(ref mime_types)) =>
+            {
+                let mut mime_overrides: Vec<String> = Vec::new();
+
+                for (extension, mime_type) in mime_types
+                {
+                    mime_overrides.push(format!("{}:{}", extension, mime_type.as_str().ok_or(String::from("mime_overrides must map extensions to strings"))?));
+                }
+
+                parameters.push(format!("--mime-overrides={}", mime_overrides.join(",")));
+            }
+            ("bucket", _) | ("region", _) | ("profile", _) | ("credentials", _) | ("prefix", _) | ("acl", _) | ("ignore", _) | ("mime_overrides", _) =>
+            {
+                return Err(format!("{} has the wrong type", name));
+            }
+            _ =>
+            {
+                eprintln!("{}: config option \"{}\" is not supported and will be ignored", stderr_paint(Yellow, "Warning"), name);
+            }
         }
     }
-}
-
-/**
- * Get a relative path for a local file
- */
-fn local_file_get_relative_path(file: &LocalFile, local_path: &String) -> String
-{
-    let full_path: String = file.path.as_path().to_str().unwrap().to_owned();
-    let last_path_section = full_path.split(local_path)
-        .last()
-        .unwrap()
-        .replace("\\", "/");
 
-    return last_path_section.to_owned();
+    return Ok((bucket_name, parameters));
 }
 
 /**
- * Upload a local file to an s3 bucket location
+ * Get the upload rules set in a TOML rules file, as [[rule]] tables with a pattern and any of acl, cache_control and content_type
  */
-fn local_file_upload_to_bucket(file: &LocalFile, local_path: &String, bucket_name: &String, public_file: bool)
+fn get_upload_rules(rules_path: &Path) -> Result<Vec<UploadRule>, String>
 {
-    let provider = ProfileProvider::with_configuration(Path::new("credentials"), "user");
-    let client = S3Client::new(default_tls_client().unwrap(), provider, Region::EuWest1);
-
-    let file = file.clone();
+    let mut contents = String::new();
+    File::open(rules_path).and_then(|mut file| file.read_to_string(&mut contents)).map_err(|error| error.to_string())?;
 
-    print!("{} \"{}\" to \"{}/{}\"...", Yellow.paint("Uploading"), file.path.to_str().unwrap(), bucket_name, local_file_get_relative_path(&file, &local_path));
-    Some(std::io::stdout().flush());
+    let rules_file = contents.parse::<toml::Value>().map_err(|error| error.to_string())?;
+    let rule_tables = match rules_file.get("rule")
+    {
+        Some(&toml::Value::Array(ref rule_tables)) => rule_tables.to_owned(),
+        Some(_) => return Err(String::from("rule must be a list of [[rule]] tables")),
+        None    => Vec::new()
+    };
 
-    let mut file_handle = File::open(&file.path).expect("Could not open file");
-    let mut contents: Vec<u8> = Vec::new();
+    let mut rules: Vec<UploadRule> = Vec::new();
 
-    match file_handle.read_to_end(&mut contents)
+    for (index, rule_table) in rule_tables.iter().enumerate()
     {
-        Ok(_) =>
-        {
-            let mut acl = String::from("private");
-            if public_file
-            {
-                acl = String::from("public-read");
-            }
+        let rule_table = rule_table.as_table().ok_or(String::from("rule must be a list of [[rule]] tables"))?;
+        let mut rule = UploadRule { pattern: String::from(""), acl: None, cache_control: None, content_type: None };
 
-            let request = PutObjectRequest
-            {
-                bucket: bucket_name.to_owned(),
-                key: local_file_get_relative_path(&file, &local_path),
-                body: Some(contents),
-                acl: Some(acl),
-                content_type: Some(local_file_get_mime(&file)),
-                ..PutObjectRequest::default()
-            };
+        for (name, value) in rule_table
+        {
+            let value = value.as_str().ok_or(format!("{} of rule {} must be a string", name, index + 1))?.trim().to_owned();
 
-            match client.put_object(&request)
+            match name.as_str()
             {
-                Ok(_) =>
-                {
-                    println!(" {}", Green.paint("DONE"));
-                }
-                Err(error) =>
+                "pattern"       => rule.pattern = value,
+                "acl"           => rule.acl = Some(value),
+                "cache_control" => rule.cache_control = Some(value),
+                "content_type"  => rule.content_type = Some(value),
+                _ =>
                 {
-                    println!(" {}: {}", Red.paint("Error"), error);
+                    eprintln!("{}: rule option \"{}\" is not supported and will be ignored", stderr_paint(Yellow, "Warning"), name);
                 }
             }
         }
-        Err(error) =>
+
+        if rule.pattern.is_empty()
+        {
+            return Err(format!("rule {} has no pattern", index + 1));
+        }
+
+        if let Some(ref acl) = rule.acl
         {
-            println!("{}: {}", Red.paint("Error"), error);
+            if !CANNED_ACLS.contains(&acl.as_str())
+            {
+                return Err(format!("the acl of rule {} must be one of {}, got \"{}\"", index + 1, CANNED_ACLS.join(", "), acl));
+            }
         }
+
+        rules.push(rule);
     }
+
+    return Ok(rules);
 }
 
 /**
- * Create the local file checksums
+ * Get the command line parameters with the subcommand and paths moved before the options, so options can be given before, between or after them
  */
-fn local_file_create_checksums(files: &Vec<LocalFile>, local_path: &String) -> LocalFile
+fn parameters_paths_first(arguments: Vec<String>) -> Vec<String>
 {
-    let mut path = PathBuf::from(local_path);
-    path.push("checksums.txt");
+    let mut arguments = arguments.into_iter();
+    let mut paths: Vec<String> = arguments.next().into_iter().collect();
+    let mut options: Vec<String> = Vec::new();
 
-    let mut checksums_file = File::create(path.to_str().unwrap()).expect("Could not create checksums");
-
-    for file in files.clone()
+    while let Some(argument) = arguments.next()
     {
-        let checksums_line = format!("{} {}\n", local_file_get_relative_path(&file, local_path), file.md5);
-        checksums_file.write(checksums_line.as_bytes()).expect("Could not write checksums");
+        if argument == "--header"
+        {
+            // The value of a --header given as a separate argument isn't a path, so it stays after its option
+            options.push(argument);
+            options.extend(arguments.next());
+        }
+        else if argument.starts_with("-")
+        {
+            options.push(argument);
+        }
+        else
+        {
+            paths.push(argument);
+        }
     }
 
-    return LocalFile { path: path, md5: String::from("") };
+    paths.extend(options);
+
+    return paths;
 }
 
 /**
- * Delete the local file checksums
+ * Struct for the hooks of a sync run from the command line, confirming the upload on the terminal unless the yes or confirm_threshold parameter is set
  */
-fn local_file_delete_checksums(local_path: &String)
+struct PromptHooks
 {
-    let mut path = PathBuf::from(local_path);
-    path.push("checksums.txt");
-
-    remove_file(path).expect("Could not remove checksums");
+    assume_yes: bool,
+    confirm_threshold: Option<usize>,
+    output_format: OutputFormat
 }
 
-/**
- * Get objects in an s3 bucket
- */
-fn get_bucket_objects(bucket_name: &String, objects: &mut Vec<Object>)
+impl SyncHooks for PromptHooks
 {
-    let provider = ProfileProvider::with_configuration(Path::new("credentials"), "user");
-    let client = S3Client::new(default_tls_client().unwrap(), provider, Region::EuWest1);
-
-    let request = ListObjectsV2Request
+    fn confirm_upload(&self, _: &RunReport, change_count: usize) -> bool
     {
-        bucket: bucket_name.to_owned(),
-        ..ListObjectsV2Request::default()
-    };
+        if self.assume_yes || self.confirm_threshold.map_or(false, |threshold| change_count <= threshold)
+        {
+            return true;
+        }
 
-    if let Ok(response) = client.list_objects_v2(&request)
-    {
-        for object in &response.contents.unwrap()
+        // Refuse if there is no terminal to ask on or the prompt can't be printed
+        if self.output_format == OutputFormat::Quiet
+        {
+            print_error("--quiet needs --yes or --dry-run, as the upload can't be confirmed without printing a prompt");
+            process::exit(EXIT_USAGE);
+        }
+
+        if !stdin_is_terminal()
+        {
+            print_error("\nstdin is not a terminal, run with --yes to upload without confirming");
+            process::exit(EXIT_USAGE);
+        }
+
+        let mut input_string = String::new();
+
+        while input_string != "y" && input_string != "n"
         {
-            objects.push(object.clone());
+            eprintln!("\nConfirm upload? <y/N>");
+
+            input_string.clear();
+            stdin().read_line(&mut input_string).expect("Did not input string");
+            input_string = String::from(input_string.trim().to_lowercase());
+
+            if input_string == "" || input_string == "no"
+            {
+                input_string = String::from("n");
+            }
+            else if input_string == "yes"
+            {
+                input_string = String::from("y");
+            }
         }
+
+        return input_string == "y";
     }
 }
 
 /**
- * Struct for checksums
+ * Main function
  */
-#[derive(Clone)]
-struct Checksums
+fn main()
 {
-    files: HashMap<String, String>
-}
+    let mut parameters: Vec<String> = parameters_paths_first(env::args().collect());
 
-/**
- * Get the s3 bucket checksums
- */
-fn get_bucket_object_checksums(bucket_name: &String, bucket_objects: &Vec<Object>) -> Option<Checksums>
-{
-    match bucket_objects.iter().position(|obj| obj.clone().key.unwrap() == String::from("checksums.txt"))
+    let list_folders = parameters.iter().any(|parameter| parameter == "--list-folders" || parameter.starts_with("--list-folders="));
+    let doctor = parameters.iter().any(|parameter| parameter == "--doctor");
+
+    // The status subcommand takes the same paths as an upload, after the subcommand name, while the download subcommand takes the bucket first
+    let status = parameters.get(1) == Some(&String::from("status"));
+    let download = parameters.get(1) == Some(&String::from("download"));
+    let path_offset = if status || download { 1 } else { 0 };
+
+    let mut config_path: Option<PathBuf> = None;
+
+    // Set the config file path if the config parameter is set, otherwise use s3-upload.toml in the local path when it exists
+    for parameter in &parameters
     {
-        Some(index) =>
+        if parameter.starts_with("--config=")
         {
-            let provider = ProfileProvider::with_configuration(Path::new("credentials"), "user");
-            let client = S3Client::new(default_tls_client().unwrap(), provider, Region::EuWest1);
+            config_path = Some(PathBuf::from(parameter.splitn(2, "=").nth(1).unwrap()));
+        }
+    }
 
-            let obj = bucket_objects.get(index).clone().unwrap();
+    if config_path.is_none() && !list_folders && !doctor
+    {
+        if let Some(local_path) = parameters.get(if download { 3 } else { 1 + path_offset }).filter(|parameter| !parameter.starts_with("-"))
+        {
+            let default_config_path = Path::new(local_path).join("s3-upload.toml");
 
-            let request = GetObjectRequest
+            if default_config_path.is_file()
             {
-                bucket: bucket_name.to_owned(),
-                key: obj.clone().key.unwrap(),
-                ..GetObjectRequest::default()
-            };
+                config_path = Some(default_config_path);
+            }
+        }
+    }
 
-            if let Ok(response) = client.get_object(&request)
+    // Insert the config file parameters after the paths, so the command line parameters that follow override them
+    if let Some(config_path) = config_path
+    {
+        match get_config_parameters(&config_path)
+        {
+            Ok((config_bucket_name, config_parameters)) =>
             {
-                let contents_bytes = response.body.unwrap();
-                let contents = String::from_utf8_lossy(&contents_bytes);
-
-                let mut hash_map = HashMap::new();
+                let mut paths_end = 1 + parameters.iter().skip(1).take_while(|parameter| !parameter.starts_with("-")).count();
 
-                for line in contents.split("\n")
+                if let Some(config_bucket_name) = config_bucket_name
                 {
-                    let mut line_split = line.split_whitespace();
-                    let path = line_split.next();
-                    let md5 = line_split.next();
-
-                    if path != None && md5 != None
+                    if !list_folders && !doctor && !download && paths_end == 2 + path_offset
                     {
-                        let path = String::from(path.unwrap());
-                        let md5 = String::from(md5.unwrap());
-                        hash_map.insert(path, md5);
+                        parameters.insert(paths_end, config_bucket_name);
+                        paths_end += 1;
                     }
                 }
 
-                let checksums = Checksums {files: hash_map};
-                Some(checksums)
+                for (index, config_parameter) in config_parameters.into_iter().enumerate()
+                {
+                    parameters.insert(paths_end + index, config_parameter);
+                }
             }
-            else
+            Err(error) =>
             {
-                None
+                print_error(&format!("config file \"{}\" could not be read: {}", config_path.display(), error));
+                process::exit(EXIT_USAGE);
             }
-        },
-        None =>
+        }
+    }
+
+    let paths_count = parameters.iter().skip(1).take_while(|parameter| !parameter.starts_with("-")).count();
+
+    if paths_count < (if list_folders || doctor { 1 } else { 2 + path_offset }) || parameters.iter().any(|parameter| parameter == "--help")
+    {
+        println!("\nUsage:\n\ns3-file-upload LOCAL_PATH BUCKET_NAME [OPTIONS]\ns3-file-upload status LOCAL_PATH BUCKET_NAME [OPTIONS]\ns3-file-upload download BUCKET_NAME LOCAL_PATH [OPTIONS]\ns3-file-upload BUCKET_NAME --list-folders[=PREFIX]\ns3-file-upload BUCKET_NAME --doctor");
+        println!("\nOptions:\n\n--ignored_directories    List of directory names using a comma separator, e.g. --ignored_directories=ignored_dir_one,ignored_dir_two");
+        println!("--ignore                 Glob patterns of paths to skip using a comma separator, e.g. --ignore='*.log,**/node_modules/**'");
+        println!("--include                Only upload files matching these glob patterns using a comma separator, e.g. --include='*.html,dist/**'");
+        println!("--follow-symlinks        Upload the files and directories symlinks point to, symlinks are skipped by default");
+        println!("--skip-hidden            Skip the files and directories whose name starts with a dot, e.g. .git, .env and .DS_Store");
+        println!("--create-dir-placeholders");
+        println!("                         Upload an empty object with a trailing slash key for each directory without files, so it shows as a folder in S3 browsers");
+        println!("--region                 AWS region of the bucket, defaults to AWS_REGION, the profile's region in ~/.aws/config or else eu-west-1, e.g. --region=us-east-1");
+        println!("--endpoint               Custom S3 compatible endpoint, e.g. --endpoint=https://minio.local:9000");
+        println!("--ca-bundle              PEM file of CA certificates to trust on top of the system ones, e.g. --ca-bundle=/etc/ssl/corp-ca.pem");
+        println!("                         Requests are sent through the proxy of HTTPS_PROXY or HTTP_PROXY when set, unless NO_PROXY lists the --endpoint host");
+        println!("--credentials            Path of the credentials file, defaults to credentials, e.g. --credentials=/home/user/.aws/credentials");
+        println!("--config                 TOML file of default options, defaults to s3-upload.toml in LOCAL_PATH, e.g. --config=deploy.toml");
+        println!("--profile                Profile to use from the credentials file, defaults to AWS_PROFILE or else user, e.g. --profile=deploy");
+        println!("--assume-role            Role assumed through STS with the credentials before the bucket is accessed, e.g. --assume-role=arn:aws:iam::123456789012:role/deploy");
+        println!("--prefix                 Key prefix the files and checksums are uploaded under, e.g. --prefix=assets/");
+        println!("--checksums-name         Name of the checksums file, defaults to checksums.txt, e.g. --checksums-name=.manifest-site-a");
+        println!("--hash                   Hash algorithm of the checksums, md5 (the default) or sha256, e.g. --hash=sha256");
+        println!("--checksums-format       Line format of the checksums file, path (the default) or md5sum, e.g. --checksums-format=md5sum");
+        println!("--checksums-local-only   Keep the checksums in a local file instead of the bucket, defaults to the checksums file in LOCAL_PATH, e.g. --checksums-local-only=../site.checksums");
+        println!("--prune-checksums        Remove the checksums entries of files that no longer exist locally, then exit without uploading");
+        println!("--delete-orphan-checksums-only");
+        println!("                         Remove the checksums entries of files whose objects are no longer in the bucket, then exit without uploading");
+        println!("--acl                    Canned ACL of the uploaded files, defaults to private, e.g. --acl=public-read");
+        println!("--cache-control          Cache-Control header of the uploaded files, e.g. --cache-control=max-age=31536000");
+        println!("--rules                  TOML file of rules setting the ACL, Cache-Control and Content-Type of the files matching a pattern, e.g. --rules=rules.toml");
+        println!("--gzip                   Gzip text files (html, css, js, json, svg...) and upload them with Content-Encoding: gzip");
+        println!("--storage-class          Storage class of the uploaded files, defaults to STANDARD, e.g. --storage-class=STANDARD_IA");
+        println!("--sse                    Server side encryption of the uploaded files, AES256 or aws:kms, e.g. --sse=AES256");
+        println!("--sse-kms-key-id         KMS key used with --sse=aws:kms instead of the default key, e.g. --sse-kms-key-id=arn:aws:kms:...");
+        println!("--dry-run                Show the files and checksums changes that would be uploaded, without uploading");
+        println!("--format                 Output format, text (the default), json for a JSON summary of the run or github for GitHub Actions annotations, e.g. --format=json");
+        println!("--verbose                Also list the files skipped as not modified and whether the bucket checksums were found");
+        println!("--quiet                  Only print errors, on stderr, e.g. for cron jobs and scripts");
+        println!("--yes, -y                Upload without asking for confirmation, required when stdin is not a terminal");
+        println!("--confirm-threshold      Upload without asking for confirmation when there are at most this many changes, e.g. --confirm-threshold=10");
+        println!("--i-know                 Upload files that may hold secrets (.env, id_rsa, *.pem...) even though the ACL is public");
+        println!("--sensitive-patterns     Replace the patterns of the files that need --i-know using a comma separator, e.g. --sensitive-patterns=.env,*.pem");
+        println!("--only-new               Only upload the new files, the modified files are listed as skipped and uploaded by a later run");
+        println!("--only-modified          Only upload the modified files, the new files are listed as skipped and uploaded by a later run");
+        println!("--delete                 Delete bucket objects that no longer exist locally, after the uploads are confirmed");
+        println!("--from-stdin             Only upload the files listed on stdin, one path relative to LOCAL_PATH per line, e.g. git diff --name-only | s3-file-upload --from-stdin -y ...");
+        println!("--only-extensions        Only upload files with these extensions using a comma separator, e.g. --only-extensions=html,css");
+        println!("--exclude-extensions     Never upload files with these extensions using a comma separator, e.g. --exclude-extensions=map,psd");
+        println!("                         Extensions are matched case-insensitively, a file must be in --only-extensions (if set) and not in --exclude-extensions, so --exclude-extensions wins when both match");
+        println!("--max-file-size          Skip the files larger than this size with a warning, e.g. --max-file-size=100MB");
+        println!("--since                  Only look at the files modified within this duration (s, m, h or d), without hashing the others, e.g. --since=1h");
+        println!("--memory-budget          Maximum bytes of file contents held in memory by uploads at once, e.g. --memory-budget=512MB");
+        println!("--multipart-threshold    Size above which files are uploaded in parts, defaults to 100MB, e.g. --multipart-threshold=1GB");
+        println!("--concurrency            Number of files uploaded at once, defaults to 4, e.g. --concurrency=8");
+        println!("--max-bandwidth          Maximum bytes per second sent by all the uploads together, e.g. --max-bandwidth=5MB");
+        println!("--max-retries            Number of retries after a network, throttling or server error, defaults to 3, e.g. --max-retries=5");
+        println!("--strip-components       Remove the first N directories of each file's path from its key, e.g. --strip-components=2");
+        println!("--timeout                Seconds a request waits for a stalled connection before failing, defaults to 300, e.g. --timeout=60");
+        println!("--cloudfront-distribution Invalidate the uploaded and deleted keys on a CloudFront distribution after the upload, e.g. --cloudfront-distribution=E2QWRUHAPOMQZL");
+        println!("--header                 Custom header sent with each uploaded file (repeatable), e.g. --header 'Cache-Control: max-age=60'");
+        println!("--content-disposition    Content-Disposition header of the uploaded files, e.g. --content-disposition=attachment");
+        println!("--metadata               Custom metadata of the uploaded files using a comma separator, e.g. --metadata=team:web,build:42");
+        println!("--tag                    Tags of the uploaded files using a comma separator, at most 10, e.g. --tag=env:prod,team:web");
+        println!("--checksums-tag          Tags of the checksums file so automation can find it, e.g. --checksums-tag=type:manifest");
+        println!("--mime-overrides         Custom mime types by extension using a comma separator, e.g. --mime-overrides=map:application/json");
+        println!("--sniff-mime             Detect the mime type of files with an unknown extension from their contents (png, jpeg, gif, pdf, html...)");
+        println!("                         --region and --profile (also from the config file) take precedence over AWS_REGION and AWS_PROFILE, which take precedence over the region in ~/.aws/config and the defaults");
+        println!("--doctor                 Check the credentials file, profile, credentials, bucket and region, then exit without uploading");
+        println!("--list-folders           List the folders at the top of the bucket (or under a prefix, e.g. --list-folders=assets/) and exit");
+
+        if parameters.iter().any(|parameter| parameter == "--help")
         {
-            None
+            return;
         }
+
+        let expected_paths = if list_folders || doctor { "BUCKET_NAME" } else if download { "BUCKET_NAME and LOCAL_PATH" } else { "LOCAL_PATH and BUCKET_NAME" };
+
+        print_error(&format!("\n{} must be given, before or after the options", expected_paths));
+        process::exit(EXIT_USAGE);
     }
-}
 
-/**
- * Check if the file matches the checksums
- */
-fn local_file_matches_checksums(local_path: &String, file: &LocalFile, checksums: &Checksums) -> FileStatus
-{
-    for (path, md5) in &checksums.files
+    let mut region = Region::EuWest1;
+
+    // Set the region from the AWS_REGION environment variable if it is set, the region parameter overrides it
+    if let Ok(environment_region) = env::var("AWS_REGION")
     {
-        if &local_file_get_relative_path(file, local_path) == path
+        if !environment_region.trim().is_empty()
         {
-            if file.md5 == md5.to_owned()
+            match Region::from_str(environment_region.trim())
             {
-                return FileStatus::NotModified;
-            }
-            else
-            {
-                return FileStatus::Modified;
+                Ok(parsed_region) =>
+                {
+                    region = parsed_region;
+                }
+                Err(error) =>
+                {
+                    // An invalid AWS_REGION is only an error when the region parameter doesn't replace it
+                    if !parameters.iter().any(|parameter| parameter.starts_with("--region="))
+                    {
+                        print_error(&format!("AWS_REGION: {}", error));
+                        process::exit(EXIT_USAGE);
+                    }
+                }
             }
         }
     }
-    return FileStatus::New;
-}
 
-/**
- * Get files from a local path
- */
-fn get_local_files(local_path: &String, files: &mut Vec<LocalFile>, ignored_directories: &mut Vec<String>)
-{
-    if let Ok(entries) = fs::read_dir(Path::new(&local_path))
+    // Set the region if the region parameter is set
+    for parameter in &parameters
     {
-        for entry in entries
+        if parameter.starts_with("--region=")
         {
-            let file = entry.unwrap();
-
-            if let Ok(metadata) = file.metadata()
+            match Region::from_str(parameter.splitn(2, "=").nth(1).unwrap())
             {
-                if metadata.is_dir()
+                Ok(parsed_region) =>
                 {
-                    if !ignored_directories.contains(&file.file_name().to_str().unwrap().to_owned())
-                    {
-                        get_local_files(&file.path().to_str().unwrap().to_owned(), files, ignored_directories);
-                    }
+                    region = parsed_region;
                 }
-                else
+                Err(error) =>
                 {
-                    let temp_file = LocalFile { path: file.path(), md5: String::from("") };
-                    let file = LocalFile { path: file.path(), md5: local_file_get_md5(&temp_file) };
-                    files.push(file);
+                    print_error(&error.to_string());
+                    process::exit(EXIT_USAGE);
                 }
             }
         }
     }
-}
 
-/**
- * Main function
- */
-fn main()
-{
-    if env::args().len() < 3
+    let mut endpoint: Option<Endpoint> = None;
+
+    // Set the custom S3 compatible endpoint if the endpoint parameter is set
+    for parameter in &parameters
     {
-        println!("\nUsage:\n\ns3-file-upload LOCAL_PATH BUCKET_NAME [OPTIONS]");
-        println!("\nOptions:\n\n--ignored_directories    List of directory names using a comma separator, e.g. --ignored_directories=ignored_dir_one,ignored_dir_two");
-        return;
+        if parameter.starts_with("--endpoint=")
+        {
+            endpoint = parse_endpoint(parameter.splitn(2, "=").nth(1).unwrap());
+
+            if endpoint.is_none()
+            {
+                print_error("--endpoint must be an http or https address, e.g. --endpoint=https://minio.local:9000");
+                process::exit(EXIT_USAGE);
+            }
+        }
     }
 
-    let local_path  = env::args().nth(1).unwrap();
-    let bucket_name = env::args().nth(2).unwrap();
+    let mut http_options = HttpOptions { proxy: None, ca_certificates: Vec::new(), timeout: Duration::from_secs(HTTP_TIMEOUT) };
 
-    if !Path::new("credentials").exists()
+    // Send the requests through the proxy of the HTTPS_PROXY or HTTP_PROXY environment variables if one is set
+    match get_environment_proxy(&endpoint)
     {
-        println!("{}", Red.paint("credentials file could not be found"));
-        return;
+        Ok(proxy) =>
+        {
+            http_options.proxy = proxy;
+        }
+        Err(error) =>
+        {
+            print_error(&error);
+            process::exit(EXIT_USAGE);
+        }
     }
 
-    let mut ignored_directories: Vec<String> = Vec::new();
+    // Set the number of seconds a stalled request waits before failing if the timeout parameter is set
+    for parameter in &parameters
+    {
+        if parameter.starts_with("--timeout=")
+        {
+            match parameter.splitn(2, "=").nth(1).unwrap().parse::<u64>()
+            {
+                Ok(seconds) if seconds > 0 =>
+                {
+                    http_options.timeout = Duration::from_secs(seconds);
+                }
+                _ =>
+                {
+                    print_error("--timeout must be a number of seconds, e.g. --timeout=60");
+                    process::exit(EXIT_USAGE);
+                }
+            }
+        }
+    }
 
-    // Set the ignored directories if the ignored_directories parameter is set
-    for parameter in env::args()
+    // Trust the certificates of a CA bundle on top of the system ones if the ca_bundle parameter is set
+    for parameter in &parameters
     {
-        if parameter.contains("--ignored_directories=")
+        if parameter.starts_with("--ca-bundle=")
         {
-            let directories = parameter.split("=").nth(1).unwrap();
+            let ca_bundle_path = parameter.splitn(2, "=").nth(1).unwrap();
+            let mut contents = String::new();
 
-            for directory in directories.split(",")
+            match File::open(ca_bundle_path).and_then(|mut file| file.read_to_string(&mut contents)).map_err(|error| error.to_string()).and_then(|_| parse_ca_bundle(&contents))
             {
-                ignored_directories.push(String::from(directory));
+                Ok(ca_certificates) =>
+                {
+                    http_options.ca_certificates = ca_certificates;
+                }
+                Err(error) =>
+                {
+                    print_error(&format!("--ca-bundle \"{}\" could not be read: {}", ca_bundle_path, error));
+                    process::exit(EXIT_USAGE);
+                }
             }
         }
     }
 
-    let mut files: Vec<LocalFile> = Vec::new();
-    get_local_files(&local_path, &mut files, &mut ignored_directories);
-
-    let mut bucket_objects: Vec<Object> = Vec::new();
-    get_bucket_objects(&bucket_name, &mut bucket_objects);
+    let mut credentials_path = String::from("credentials");
+    let mut profile = String::from("user");
 
-    let checksums = match get_bucket_object_checksums(&bucket_name, &bucket_objects)
+    // Set the profile from the AWS_PROFILE environment variable if it is set, the profile parameter overrides it
+    if let Ok(environment_profile) = env::var("AWS_PROFILE")
     {
-        Some(checksums) =>
+        if !environment_profile.trim().is_empty()
         {
-            Some(checksums)
-        },
-        None =>
-        {
-            let hash_map = HashMap::new();
-            let checksums = Checksums {files: hash_map};
-            Some(checksums)
+            profile = String::from(environment_profile.trim());
         }
-    }.unwrap();
-
-    let mut new_files: Vec<LocalFile> = Vec::new();
-    let mut modified_files: Vec<LocalFile> = Vec::new();
+    }
 
-    for file in &files
+    // Set the credentials file path and profile if the credentials or profile parameters are set
+    for parameter in &parameters
     {
-        let file_status: FileStatus = local_file_matches_checksums(&local_path, &file, &checksums);
-        if file_status == FileStatus::New
+        if parameter.starts_with("--credentials=")
         {
-            new_files.push(file.to_owned());
+            credentials_path = String::from(parameter.splitn(2, "=").nth(1).unwrap());
         }
-        else if file_status == FileStatus::Modified
+        else if parameter.starts_with("--profile=")
         {
-            modified_files.push(file.to_owned());
+            profile = String::from(parameter.splitn(2, "=").nth(1).unwrap());
         }
     }
 
-    if modified_files.len() > 0 || new_files.len() > 0
-    {
-        println!("\nFiles found to be uploaded:\n");
-
-        for file in &new_files
-        {
-            println!("{}:      {}", Green.paint("New"), local_file_get_relative_path(file, &local_path));
-        }
+    // Set the region from the profile in the AWS config file if neither AWS_REGION nor the region parameter set it, as the AWS CLI does
+    let environment_region_set = env::var("AWS_REGION").map(|environment_region| !environment_region.trim().is_empty()).unwrap_or(false);
 
-        for file in &modified_files
+    if !environment_region_set && !parameters.iter().any(|parameter| parameter.starts_with("--region="))
+    {
+        if let Some(aws_config_path) = get_aws_config_path()
         {
-            println!("{}: {}", Green.paint("Modified"), local_file_get_relative_path(file, &local_path));
+            if let Some(config_region) = get_profile_config_region(&aws_config_path, &profile)
+            {
+                match Region::from_str(&config_region)
+                {
+                    Ok(parsed_region) =>
+                    {
+                        region = parsed_region;
+                    }
+                    Err(error) =>
+                    {
+                        print_error(&format!("{}: {}", aws_config_path.display(), error));
+                        process::exit(EXIT_USAGE);
+                    }
+                }
+            }
         }
+    }
 
-        let mut input_string = String::new();
+    let mut assume_role: Option<String> = None;
 
-        while input_string != "y" && input_string != "n"
+    // Set the role assumed with the credentials if the assume_role parameter is set
+    for parameter in &parameters
+    {
+        if parameter.starts_with("--assume-role=")
         {
-            println!("\nConfirm upload? <y/N>");
-
-            input_string.clear();
-            stdin().read_line(&mut input_string).expect("Did not input string");
-            input_string = String::from(input_string.trim().to_lowercase());
+            let role_arn = parameter.splitn(2, "=").nth(1).unwrap();
 
-            if input_string == "" || input_string == "no"
+            if !role_arn.starts_with("arn:")
             {
-                input_string = String::from("n");
+                print_error(&format!("--assume-role must be the ARN of a role, e.g. --assume-role=arn:aws:iam::123456789012:role/deploy, got \"{}\"", role_arn));
+                process::exit(EXIT_USAGE);
             }
-            else if input_string == "yes"
+
+            // S3 compatible servers don't provide STS, so there is no role to assume on a custom endpoint
+            if endpoint.is_some()
             {
-                input_string = String::from("y");
+                print_error("--assume-role can't be used with --endpoint");
+                process::exit(EXIT_USAGE);
             }
+
+            assume_role = Some(String::from(role_arn));
         }
+    }
 
-        let confirm_upload = input_string == "y";
+    let mut cloudfront_distribution: Option<String> = None;
 
-        if confirm_upload
+    // Set the CloudFront distribution invalidated after the upload if the cloudfront_distribution parameter is set
+    for parameter in &parameters
+    {
+        if parameter.starts_with("--cloudfront-distribution=")
         {
-            println!("");
+            let distribution_id = parameter.splitn(2, "=").nth(1).unwrap();
 
-            for file in &modified_files
+            if distribution_id.is_empty() || !distribution_id.chars().all(|character| character.is_ascii_alphanumeric())
             {
-                local_file_upload_to_bucket(&file, &local_path, &bucket_name, true);
+                print_error(&format!("--cloudfront-distribution must be the ID of a distribution, e.g. --cloudfront-distribution=E2QWRUHAPOMQZL, got \"{}\"", distribution_id));
+                process::exit(EXIT_USAGE);
             }
 
-            for file in &new_files
+            // A custom endpoint's credentials aren't AWS ones, so they can't be used for CloudFront
+            if endpoint.is_some()
             {
-                local_file_upload_to_bucket(&file, &local_path, &bucket_name, true);
+                print_error("--cloudfront-distribution can't be used with --endpoint");
+                process::exit(EXIT_USAGE);
             }
 
-            let new_checksums = local_file_create_checksums(&files, &local_path);
-            local_file_upload_to_bucket(&new_checksums, &local_path, &bucket_name, false);
-            local_file_delete_checksums(&local_path);
+            cloudfront_distribution = Some(String::from(distribution_id));
+        }
+    }
 
-            println!("\n{}", Green.paint("UPLOAD COMPLETE"))
+    // Check the configuration instead of uploading if the doctor parameter is set
+    if doctor
+    {
+        let bucket_name = parameters[1].to_owned();
+        if !run_doctor(&bucket_name, region, &credentials_path, &profile, &assume_role, &endpoint, &http_options)
+        {
+            process::exit(EXIT_FAILURE);
         }
-        else
+
+        return;
+    }
+
+    let local_path  = parameters[if download { 3 } else { 1 + path_offset }].to_owned();
+    let bucket_name = parameters[if download { 2 } else { 2 + path_offset }].to_owned();
+
+    if !Path::new(&credentials_path).exists() && !environment_credentials_exist()
+    {
+        print_error(&format!("credentials file \"{}\" could not be found and AWS_ACCESS_KEY_ID/AWS_SECRET_ACCESS_KEY are not set", credentials_path));
+        process::exit(EXIT_USAGE);
+    }
+
+    let mut bandwidth_limit: Option<Arc<BandwidthLimit>> = None;
+
+    // Set the bandwidth limit shared by every upload if the max_bandwidth parameter is set
+    for parameter in &parameters
+    {
+        if parameter.starts_with("--max-bandwidth=")
         {
-            println!("\n{}", Yellow.paint("UPLOAD CANCELLED"))
+            match parse_size(parameter.splitn(2, "=").nth(1).unwrap())
+            {
+                Some(bytes_per_second) if bytes_per_second > 0 =>
+                {
+                    bandwidth_limit = Some(Arc::new(BandwidthLimit { bytes_per_second: bytes_per_second, next_send: Mutex::new(Instant::now()) }));
+                }
+                _ =>
+                {
+                    print_error("--max-bandwidth must be a number of bytes per second, e.g. --max-bandwidth=5MB");
+                    process::exit(EXIT_USAGE);
+                }
+            }
         }
     }
-    else
+
+    // Create the S3 client once and share it between every bucket operation
+    let client: Arc<dyn ObjectStore> = Arc::new(create_bucket_client(region, &credentials_path, &profile, &assume_role, &endpoint, &bandwidth_limit, &http_options));
+
+    // Check the bucket can be reached before the local files are walked and hashed, which can take minutes for a large tree, a dry run only warns
+    let checked_bucket_name = if list_folders { parameters[1].to_owned() } else { bucket_name.to_owned() };
+
+    if let Err(error) = check_bucket_access(&*client, &checked_bucket_name)
+    {
+        if parameters.iter().any(|parameter| parameter == "--dry-run")
+        {
+            eprintln!("{}: {}", stderr_paint(Yellow, "Warning"), error);
+        }
+        else
+        {
+            print_error(&format!("Error: {}", error));
+            process::exit(EXIT_FAILURE);
+        }
+    }
+
+    // List the bucket folders instead of uploading if the list_folders parameter is set
+    if list_folders
+    {
+        let bucket_name = parameters[1].to_owned();
+        let mut prefix = String::from("");
+
+        for parameter in &parameters
+        {
+            if parameter.starts_with("--list-folders=")
+            {
+                prefix = String::from(parameter.splitn(2, "=").nth(1).unwrap());
+            }
+        }
+
+        let mut folders: Vec<String> = Vec::new();
+        let listed = get_bucket_folders(&*client, &bucket_name, &prefix, &mut folders);
+
+        println!("\nFolders in \"{}/{}\":\n", bucket_name, prefix);
+
+        for folder in &folders
+        {
+            println!("{}", folder);
+        }
+
+        if !listed
+        {
+            process::exit(EXIT_FAILURE);
+        }
+
+        if folders.len() == 0
+        {
+            println!("{}", Yellow.paint("No folders found"));
+        }
+
+        return;
+    }
+
+    let mut checksums_name = String::from("checksums.txt");
+
+    // Set the name of the checksums file if the checksums_name parameter is set
+    for parameter in &parameters
+    {
+        if parameter.starts_with("--checksums-name=")
+        {
+            checksums_name = String::from(parameter.splitn(2, "=").nth(1).unwrap().trim());
+
+            if !checksums_name_is_valid(&checksums_name)
+            {
+                print_error("--checksums-name must be a file name without a /, e.g. --checksums-name=.manifest-site-a");
+                process::exit(EXIT_USAGE);
+            }
+        }
+    }
+
+    // Download the bucket objects to the local path instead of uploading if the download subcommand is given
+    if download
+    {
+        let mut prefix = String::from("");
+
+        for parameter in &parameters
+        {
+            if parameter.starts_with("--prefix=")
+            {
+                prefix = parse_prefix(parameter.splitn(2, "=").nth(1).unwrap());
+            }
+        }
+
+        let dry_run = parameters.iter().any(|parameter| parameter == "--dry-run");
+
+        if !run_download(&*client, &bucket_name, &prefix, &checksums_name, &local_path, dry_run)
+        {
+            process::exit(EXIT_FAILURE);
+        }
+
+        return;
+    }
+
+    // The local path is made canonical once, so ./site, ./site/ and /path/to/site all give the same keys
+    let local_path = match local_path_canonicalize(&local_path)
+    {
+        Ok(canonical_path) => canonical_path,
+        Err(error) =>
+        {
+            print_error(&format!("local path \"{}\" could not be read: {}", local_path, error));
+            process::exit(EXIT_USAGE);
+        }
+    };
+
+    // A single file is uploaded from the directory it is in, so its key is its file name
+    let single_file_path = if Path::new(&local_path).is_file() { Some(PathBuf::from(&local_path)) } else { None };
+
+    let local_path = match single_file_path
+    {
+        Some(ref path) => String::from(path.parent().unwrap().to_str().unwrap()),
+        None => local_path
+    };
+
+    let mut ignored_directories: Vec<String> = Vec::new();
+
+    // Set the ignored directories if the ignored_directories parameter is set
+    for parameter in &parameters
+    {
+        if parameter.contains("--ignored_directories=")
+        {
+            let directories = parameter.split("=").nth(1).unwrap();
+
+            for directory in directories.split(",")
+            {
+                ignored_directories.push(String::from(directory));
+            }
+        }
+    }
+
+    let mut ignore_patterns: Vec<String> = Vec::new();
+
+    // Add to the .s3ignore patterns, which sync() reads itself, if the ignore parameter is set
+    for parameter in &parameters
+    {
+        if parameter.starts_with("--ignore=")
+        {
+            for ignore_pattern in parameter.splitn(2, "=").nth(1).unwrap().split(",")
+            {
+                if !ignore_pattern.trim().is_empty()
+                {
+                    ignore_patterns.push(String::from(ignore_pattern.trim()));
+                }
+            }
+        }
+    }
+
+    let mut checksums_local_path: Option<PathBuf> = None;
+
+    // Keep the checksums in a local file instead of uploading them if the checksums_local_only parameter is set, by default the checksums file in LOCAL_PATH
+    for parameter in &parameters
+    {
+        if parameter == "--checksums-local-only"
+        {
+            checksums_local_path = Some(Path::new(&local_path).join(&checksums_name));
+        }
+        else if parameter.starts_with("--checksums-local-only=")
+        {
+            let path = PathBuf::from(parameter.splitn(2, "=").nth(1).unwrap().trim());
+            let directory = match path.parent()
+            {
+                Some(directory) if !directory.as_os_str().is_empty() => directory.to_owned(),
+                _ => PathBuf::from(".")
+            };
+
+            if path.file_name().is_none() || !directory.is_dir()
+            {
+                print_error(&format!("--checksums-local-only must be a file path in an existing directory, got \"{}\"", path.display()));
+                process::exit(EXIT_USAGE);
+            }
+
+            // A checksums file kept inside LOCAL_PATH is left out of the upload, as the default one is
+            if let (Ok(local_directory), Ok(checksums_directory)) = (Path::new(&local_path).canonicalize(), directory.canonicalize())
+            {
+                if let Ok(relative_directory) = checksums_directory.strip_prefix(&local_directory)
+                {
+                    let relative_path = relative_directory.join(path.file_name().unwrap());
+                    ignore_patterns.push(format!("/{}", relative_path.to_string_lossy().replace("\\", "/")));
+                }
+            }
+
+            checksums_local_path = Some(directory.join(path.file_name().unwrap()));
+        }
+    }
+
+    let mut include_patterns: Vec<String> = Vec::new();
+
+    // Set the patterns of the only files to upload if the include parameter is set
+    for parameter in &parameters
+    {
+        if parameter.starts_with("--include=")
+        {
+            for include_pattern in parameter.splitn(2, "=").nth(1).unwrap().split(",")
+            {
+                if !include_pattern.trim().is_empty()
+                {
+                    include_patterns.push(String::from(include_pattern.trim()));
+                }
+            }
+        }
+    }
+
+    let mut sensitive_patterns: Vec<String> = SENSITIVE_PATTERNS.iter().map(|pattern| String::from(*pattern)).collect();
+
+    // Replace the default sensitive patterns if the sensitive_patterns parameter is set, an empty list turns the check off
+    for parameter in &parameters
+    {
+        if parameter.starts_with("--sensitive-patterns=")
+        {
+            sensitive_patterns = parameter.splitn(2, "=").nth(1).unwrap().split(",").map(|pattern| String::from(pattern.trim())).filter(|pattern| !pattern.is_empty()).collect();
+        }
+    }
+
+    let mut only_extensions: Vec<String> = Vec::new();
+    let mut exclude_extensions: Vec<String> = Vec::new();
+
+    // Set the extension allow and deny lists if the only_extensions or exclude_extensions parameters are set
+    for parameter in &parameters
+    {
+        if parameter.starts_with("--only-extensions=")
+        {
+            only_extensions.append(&mut parse_extensions(parameter.splitn(2, "=").nth(1).unwrap()));
+        }
+        else if parameter.starts_with("--exclude-extensions=")
+        {
+            exclude_extensions.append(&mut parse_extensions(parameter.splitn(2, "=").nth(1).unwrap()));
+        }
+    }
+
+    let mut memory_budget: u64 = std::u64::MAX;
+
+    // Set the memory budget for upload bodies if the memory_budget parameter is set
+    for parameter in &parameters
+    {
+        if parameter.starts_with("--memory-budget=")
+        {
+            match parse_size(parameter.splitn(2, "=").nth(1).unwrap())
+            {
+                Some(limit) if limit > 0 =>
+                {
+                    memory_budget = limit;
+                }
+                _ =>
+                {
+                    print_error("--memory-budget must be a number of bytes, e.g. --memory-budget=512MB");
+                    process::exit(EXIT_USAGE);
+                }
+            }
+        }
+    }
+
+    let mut multipart_threshold = MULTIPART_THRESHOLD;
+
+    // Set the size above which files are uploaded in parts if the multipart_threshold parameter is set
+    for parameter in &parameters
+    {
+        if parameter.starts_with("--multipart-threshold=")
+        {
+            match parse_size(parameter.splitn(2, "=").nth(1).unwrap())
+            {
+                Some(threshold) =>
+                {
+                    multipart_threshold = threshold;
+                }
+                None =>
+                {
+                    print_error("--multipart-threshold must be a number of bytes, e.g. --multipart-threshold=100MB");
+                    process::exit(EXIT_USAGE);
+                }
+            }
+        }
+    }
+
+    let mut max_file_size: Option<u64> = None;
+
+    // Set the size above which files are skipped instead of uploaded if the max_file_size parameter is set
+    for parameter in &parameters
+    {
+        if parameter.starts_with("--max-file-size=")
+        {
+            match parse_size(parameter.splitn(2, "=").nth(1).unwrap())
+            {
+                Some(size) =>
+                {
+                    max_file_size = Some(size);
+                }
+                None =>
+                {
+                    print_error("--max-file-size must be a number of bytes, e.g. --max-file-size=100MB");
+                    process::exit(EXIT_USAGE);
+                }
+            }
+        }
+    }
+
+    let mut since: Option<Duration> = None;
+
+    // Set how recently a file must have been modified to be looked at if the since parameter is set
+    for parameter in &parameters
+    {
+        if parameter.starts_with("--since=")
+        {
+            match parse_duration(parameter.splitn(2, "=").nth(1).unwrap())
+            {
+                Some(duration) =>
+                {
+                    since = Some(duration);
+                }
+                None =>
+                {
+                    print_error("--since must be a duration in s, m, h or d, e.g. --since=1h");
+                    process::exit(EXIT_USAGE);
+                }
+            }
+        }
+    }
+
+    let mut max_retries = MAX_RETRIES;
+
+    // Set the number of retries after a transient upload error if the max_retries parameter is set
+    for parameter in &parameters
+    {
+        if parameter.starts_with("--max-retries=")
+        {
+            match parameter.splitn(2, "=").nth(1).unwrap().parse::<u32>()
+            {
+                Ok(retries) =>
+                {
+                    max_retries = retries;
+                }
+                Err(_) =>
+                {
+                    print_error("--max-retries must be a number, e.g. --max-retries=5");
+                    process::exit(EXIT_USAGE);
+                }
+            }
+        }
+    }
+
+    let mut strip_components: usize = 0;
+
+    // Set the number of leading directories removed from the keys if the strip_components parameter is set
+    for parameter in &parameters
+    {
+        if parameter.starts_with("--strip-components=")
+        {
+            match parameter.splitn(2, "=").nth(1).unwrap().parse::<usize>()
+            {
+                Ok(components) =>
+                {
+                    strip_components = components;
+                }
+                Err(_) =>
+                {
+                    print_error("--strip-components must be a number, e.g. --strip-components=2");
+                    process::exit(EXIT_USAGE);
+                }
+            }
+        }
+    }
+
+    let mut concurrency: usize = 4;
+
+    // Set the number of upload worker threads if the concurrency parameter is set
+    for parameter in &parameters
+    {
+        if parameter.starts_with("--concurrency=")
+        {
+            match parameter.splitn(2, "=").nth(1).unwrap().parse::<usize>()
+            {
+                Ok(workers) if workers > 0 =>
+                {
+                    concurrency = workers;
+                }
+                _ =>
+                {
+                    print_error("--concurrency must be a number greater than 0, e.g. --concurrency=8");
+                    process::exit(EXIT_USAGE);
+                }
+            }
+        }
+    }
+
+    let mut headers: Vec<(String, String)> = Vec::new();
+
+    // Set the custom upload headers if any header parameters are set, either as --header=VALUE or --header VALUE
+    for (index, parameter) in parameters.iter().enumerate()
+    {
+        let header = if parameter.starts_with("--header=")
+        {
+            parameter.splitn(2, "=").nth(1)
+        }
+        else if parameter == "--header"
+        {
+            parameters.get(index + 1).map(|value| value.as_str())
+        }
+        else
+        {
+            None
+        };
+
+        if let Some(header) = header
+        {
+            let mut header_split = header.splitn(2, ":");
+
+            match (header_split.next(), header_split.next())
+            {
+                (Some(name), Some(value)) if !name.trim().is_empty() =>
+                {
+                    if !put_object_request_set_header(&mut PutObjectRequest::default(), name, value)
+                    {
+                        eprintln!("{}: header \"{}\" is not supported by the S3 client and will not be sent", stderr_paint(Yellow, "Warning"), name.trim());
+                    }
+
+                    headers.push((String::from(name.trim()), String::from(value.trim())));
+                }
+                _ =>
+                {
+                    print_error(&format!("--header must be in the form 'Name: Value', got \"{}\"", header));
+                    process::exit(EXIT_USAGE);
+                }
+            }
+        }
+    }
+
+    // Set the Content-Disposition header and metadata of the uploaded files if the content_disposition or metadata parameters are set
+    for parameter in &parameters
+    {
+        if parameter.starts_with("--content-disposition=")
+        {
+            let content_disposition = parameter.splitn(2, "=").nth(1).unwrap().trim();
+
+            if !header_value_is_valid(content_disposition)
+            {
+                print_error(&format!("--content-disposition can only contain printable ASCII characters, got \"{}\"", content_disposition));
+                process::exit(EXIT_USAGE);
+            }
+
+            headers.push((String::from("Content-Disposition"), String::from(content_disposition)));
+        }
+        else if parameter.starts_with("--metadata=")
+        {
+            for metadata in parameter.splitn(2, "=").nth(1).unwrap().split(",")
+            {
+                let mut metadata_split = metadata.splitn(2, ":");
+
+                match (metadata_split.next(), metadata_split.next())
+                {
+                    (Some(key), Some(value)) if header_name_is_valid(key.trim()) && header_value_is_valid(value.trim()) =>
+                    {
+                        headers.push((format!("x-amz-meta-{}", key.trim().to_lowercase()), String::from(value.trim())));
+                    }
+                    _ =>
+                    {
+                        print_error(&format!("--metadata must be in the form key:value with a key of letters, digits and -_. and a printable ASCII value, got \"{}\"", metadata));
+                        process::exit(EXIT_USAGE);
+                    }
+                }
+            }
+        }
+        else if parameter.starts_with("--tag=")
+        {
+            match parse_tags(parameter.splitn(2, "=").nth(1).unwrap())
+            {
+                Ok(ref tagging) if !tagging.is_empty() =>
+                {
+                    headers.push((String::from("x-amz-tagging"), tagging.to_owned()));
+                }
+                Ok(_) => {}
+                Err(error) =>
+                {
+                    print_error(&format!("--tag {}", error));
+                    process::exit(EXIT_USAGE);
+                }
+            }
+        }
+    }
+
+    let mut checksums_tagging: Option<String> = None;
+
+    // Set the tags of the checksums file if the checksums_tag parameter is set
+    for parameter in &parameters
+    {
+        if parameter.starts_with("--checksums-tag=")
+        {
+            match parse_tags(parameter.splitn(2, "=").nth(1).unwrap())
+            {
+                Ok(tagging) =>
+                {
+                    checksums_tagging = Some(tagging).filter(|tagging| !tagging.is_empty());
+                }
+                Err(error) =>
+                {
+                    print_error(&format!("--checksums-tag {}", error));
+                    process::exit(EXIT_USAGE);
+                }
+            }
+        }
+    }
+
+    let mut mime_overrides: HashMap<String, String> = HashMap::new();
+
+    // Set the custom mime types if the mime_overrides parameter is set, e.g. --mime-overrides=map:application/json
+    for parameter in &parameters
+    {
+        if parameter.starts_with("--mime-overrides=")
+        {
+            for mime_override in parameter.splitn(2, "=").nth(1).unwrap().split(",")
+            {
+                let mut override_split = mime_override.splitn(2, ":");
+
+                match (override_split.next(), override_split.next())
+                {
+                    (Some(extension), Some(mime_type)) if !extension.trim().trim_start_matches('.').is_empty() && !mime_type.trim().is_empty() =>
+                    {
+                        mime_overrides.insert(extension.trim().trim_start_matches('.').to_lowercase(), String::from(mime_type.trim()));
+                    }
+                    _ =>
+                    {
+                        print_error(&format!("--mime-overrides must be in the form extension:type, got \"{}\"", mime_override));
+                        process::exit(EXIT_USAGE);
+                    }
+                }
+            }
+        }
+    }
+
+    let mut prefix = String::from("");
+
+    // Set the key prefix the files are uploaded under if the prefix parameter is set
+    for parameter in &parameters
+    {
+        if parameter.starts_with("--prefix=")
+        {
+            prefix = parse_prefix(parameter.splitn(2, "=").nth(1).unwrap());
+        }
+    }
+
+    let mut acl = String::from("private");
+
+    // Set the canned ACL of the uploaded files if the acl parameter is set
+    for parameter in &parameters
+    {
+        if parameter.starts_with("--acl=")
+        {
+            acl = String::from(parameter.splitn(2, "=").nth(1).unwrap());
+
+            if !CANNED_ACLS.contains(&acl.as_str())
+            {
+                print_error(&format!("--acl must be one of {}, got \"{}\"", CANNED_ACLS.join(", "), acl));
+                process::exit(EXIT_USAGE);
+            }
+        }
+    }
+
+    let mut rules: Vec<UploadRule> = Vec::new();
+
+    // Set the rules overriding the options of the files matching their patterns if the rules parameter is set
+    for parameter in &parameters
+    {
+        if parameter.starts_with("--rules=")
+        {
+            let rules_path = parameter.splitn(2, "=").nth(1).unwrap();
+
+            match get_upload_rules(Path::new(rules_path))
+            {
+                Ok(file_rules) =>
+                {
+                    rules = file_rules;
+                }
+                Err(error) =>
+                {
+                    print_error(&format!("rules file \"{}\" could not be read: {}", rules_path, error));
+                    process::exit(EXIT_USAGE);
+                }
+            }
+        }
+    }
+
+    let mut cache_control: Option<String> = None;
+
+    // Set the Cache-Control header of the uploaded files if the cache_control parameter is set
+    for parameter in &parameters
+    {
+        if parameter.starts_with("--cache-control=")
+        {
+            cache_control = Some(String::from(parameter.splitn(2, "=").nth(1).unwrap().trim()));
+        }
+    }
+
+    let gzip = parameters.iter().any(|parameter| parameter == "--gzip");
+    let sniff_mime = parameters.iter().any(|parameter| parameter == "--sniff-mime");
+
+    let mut hash_algorithm = HashAlgorithm::Md5;
+
+    // Set the hash algorithm of the checksums if the hash parameter is set
+    for parameter in &parameters
+    {
+        if parameter.starts_with("--hash=")
+        {
+            match parse_hash_algorithm(parameter.splitn(2, "=").nth(1).unwrap().trim())
+            {
+                Some(algorithm) =>
+                {
+                    hash_algorithm = algorithm;
+                }
+                None =>
+                {
+                    print_error("--hash must be md5 or sha256, e.g. --hash=sha256");
+                    process::exit(EXIT_USAGE);
+                }
+            }
+        }
+    }
+
+    let mut checksums_format = ChecksumsFormat::Path;
+
+    // Write the checksums in the GNU md5sum format if the checksums_format parameter is set
+    for parameter in &parameters
+    {
+        if parameter.starts_with("--checksums-format=")
+        {
+            match parse_checksums_format(parameter.splitn(2, "=").nth(1).unwrap().trim())
+            {
+                Some(format) =>
+                {
+                    checksums_format = format;
+                }
+                None =>
+                {
+                    print_error("--checksums-format must be path or md5sum, e.g. --checksums-format=md5sum");
+                    process::exit(EXIT_USAGE);
+                }
+            }
+        }
+    }
+
+    // The checksums of compressed files are taken over their compressed upload body, so md5sum -c would fail for each of them
+    if checksums_format == ChecksumsFormat::Md5sum && gzip
+    {
+        print_error("--checksums-format=md5sum can't be used with --gzip, as the checksums of compressed files don't match the local files");
+        process::exit(EXIT_USAGE);
+    }
+
+    let mut storage_class: Option<String> = None;
+
+    // Set the storage class of the uploaded files if the storage_class parameter is set
+    for parameter in &parameters
+    {
+        if parameter.starts_with("--storage-class=")
+        {
+            let class = parameter.splitn(2, "=").nth(1).unwrap().trim().to_uppercase();
+
+            if !STORAGE_CLASSES.contains(&class.as_str())
+            {
+                print_error(&format!("--storage-class must be one of {}, got \"{}\"", STORAGE_CLASSES.join(", "), class));
+                process::exit(EXIT_USAGE);
+            }
+
+            storage_class = Some(class);
+        }
+    }
+
+    let mut server_side_encryption: Option<String> = None;
+    let mut ssekms_key_id: Option<String> = None;
+
+    // Set the server side encryption of the uploaded files if the sse or sse_kms_key_id parameters are set
+    for parameter in &parameters
+    {
+        if parameter.starts_with("--sse=")
+        {
+            let encryption = String::from(parameter.splitn(2, "=").nth(1).unwrap().trim());
+
+            if encryption != "AES256" && encryption != "aws:kms"
+            {
+                print_error(&format!("--sse must be AES256 or aws:kms, got \"{}\"", encryption));
+                process::exit(EXIT_USAGE);
+            }
+
+            server_side_encryption = Some(encryption);
+        }
+        else if parameter.starts_with("--sse-kms-key-id=")
+        {
+            ssekms_key_id = Some(String::from(parameter.splitn(2, "=").nth(1).unwrap().trim()));
+        }
+    }
+
+    // A KMS key implies KMS encryption, and KMS encryption without a key uses the bucket's default KMS key
+    if ssekms_key_id.is_some()
+    {
+        if server_side_encryption == Some(String::from("AES256"))
+        {
+            print_error("--sse-kms-key-id can only be used with --sse=aws:kms");
+            process::exit(EXIT_USAGE);
+        }
+
+        server_side_encryption = Some(String::from("aws:kms"));
+    }
+
+    let upload_options = UploadOptions
+    {
+        prefix: prefix,
+        acl: acl,
+        cache_control: cache_control,
+        gzip: gzip,
+        storage_class: storage_class,
+        server_side_encryption: server_side_encryption,
+        ssekms_key_id: ssekms_key_id,
+        headers: headers,
+        mime_overrides: mime_overrides,
+        sniff_mime: sniff_mime,
+        hash_algorithm: hash_algorithm,
+        checksums_format: checksums_format,
+        multipart_threshold: multipart_threshold,
+        max_retries: max_retries,
+        checksums_tagging: checksums_tagging,
+        strip_components: strip_components,
+        rules: rules
+    };
+
+    let mut output_format = OutputFormat::Text;
+
+    // Set the output format if the format parameter is set
+    for parameter in &parameters
+    {
+        if parameter.starts_with("--format=")
+        {
+            output_format = match parameter.splitn(2, "=").nth(1).unwrap()
+            {
+                "text" => OutputFormat::Text,
+                "json" => OutputFormat::Json,
+                "github" => OutputFormat::Github,
+                format =>
+                {
+                    print_error(&format!("--format must be text, json or github, got \"{}\"", format));
+                    process::exit(EXIT_USAGE);
+                }
+            };
+        }
+    }
+
+    let dry_run = parameters.iter().any(|parameter| parameter == "--dry-run");
+    let prune_checksums = parameters.iter().any(|parameter| parameter == "--prune-checksums");
+    let delete_orphan_checksums = parameters.iter().any(|parameter| parameter == "--delete-orphan-checksums-only");
+
+    if prune_checksums && delete_orphan_checksums
+    {
+        print_error("--prune-checksums and --delete-orphan-checksums-only can't be used together");
+        process::exit(EXIT_USAGE);
+    }
+    let verbose = parameters.iter().any(|parameter| parameter == "--verbose");
+
+    // Only print errors if the quiet parameter is set, which can't be combined with output that asks for more
+    if parameters.iter().any(|parameter| parameter == "--quiet")
+    {
+        if verbose
+        {
+            print_error("--quiet and --verbose can't be used together");
+            process::exit(EXIT_USAGE);
+        }
+
+        if output_format == OutputFormat::Json || output_format == OutputFormat::Github
+        {
+            print_error("--quiet and --format=json or --format=github can't be used together");
+            process::exit(EXIT_USAGE);
+        }
+
+        output_format = OutputFormat::Quiet;
+    }
+    let delete = parameters.iter().any(|parameter| parameter == "--delete");
+    let only_new = parameters.iter().any(|parameter| parameter == "--only-new");
+    let only_modified = parameters.iter().any(|parameter| parameter == "--only-modified");
+
+    if only_new && only_modified
+    {
+        print_error("--only-new and --only-modified can't be used together");
+        process::exit(EXIT_USAGE);
+    }
+
+    let assume_yes = parameters.iter().any(|parameter| parameter == "--yes" || parameter == "-y");
+    let mut confirm_threshold: Option<usize> = None;
+
+    // Set the number of changes confirmed without asking if the confirm_threshold parameter is set
+    for parameter in &parameters
+    {
+        if parameter.starts_with("--confirm-threshold=")
+        {
+            match parameter.splitn(2, "=").nth(1).unwrap().parse::<usize>()
+            {
+                Ok(threshold) =>
+                {
+                    confirm_threshold = Some(threshold);
+                }
+                Err(_) =>
+                {
+                    print_error("--confirm-threshold must be a number of files, e.g. --confirm-threshold=10");
+                    process::exit(EXIT_USAGE);
+                }
+            }
+        }
+    }
+
+    // Every other file in the bucket would be missing from a single file upload, so it can't be used to delete or prune them
+    if single_file_path.is_some() && (delete || prune_checksums)
+    {
+        print_error("--delete and --prune-checksums can't be used when LOCAL_PATH is a single file");
+        process::exit(EXIT_USAGE);
+    }
+
+    // The files read from stdin or modified since the since parameter are only part of the tree, so the checksums entries and objects of the others are kept
+    let from_stdin = parameters.iter().any(|parameter| parameter == "--from-stdin");
+
+    if from_stdin && single_file_path.is_some()
+    {
+        print_error("--from-stdin can't be used when LOCAL_PATH is a single file");
+        process::exit(EXIT_USAGE);
+    }
+
+    if from_stdin && (delete || prune_checksums)
+    {
+        print_error("--delete and --prune-checksums can't be used with --from-stdin");
+        process::exit(EXIT_USAGE);
+    }
+
+    if since.is_some() && (delete || prune_checksums)
+    {
+        print_error("--delete and --prune-checksums can't be used with --since");
+        process::exit(EXIT_USAGE);
+    }
+
+    // Only a walked directory tree has empty directories to create placeholders for
+    let create_dir_placeholders = parameters.iter().any(|parameter| parameter == "--create-dir-placeholders");
+
+    if create_dir_placeholders && (single_file_path.is_some() || from_stdin)
+    {
+        print_error("--create-dir-placeholders can't be used with --from-stdin or when LOCAL_PATH is a single file");
+        process::exit(EXIT_USAGE);
+    }
+
+    // A single file is uploaded under its file name, it has no directories to strip
+    if single_file_path.is_some() && strip_components > 0
+    {
+        print_error("--strip-components can't be used when LOCAL_PATH is a single file");
+        process::exit(EXIT_USAGE);
+    }
+
+    let mut listed_paths: Option<Vec<String>> = None;
+
+    if from_stdin
+    {
+        let mut paths: Vec<String> = Vec::new();
+
+        // The lines are read as bytes, so a path that isn't valid UTF-8 is skipped with a warning rather than stopping the run
+        for line in stdin().lock().split(b'\n')
+        {
+            match line.map(String::from_utf8)
+            {
+                Ok(Ok(path)) => paths.push(path),
+                Ok(Err(error)) =>
+                {
+                    eprintln!("{}: skipping \"{}\" from stdin, it is not valid UTF-8", stderr_paint(Yellow, "Warning"), String::from_utf8_lossy(error.as_bytes()).trim());
+                }
+                Err(error) =>
+                {
+                    print_error(&format!("Could not read the paths from stdin: {}", error));
+                    process::exit(EXIT_FAILURE);
+                }
+            }
+        }
+
+        listed_paths = Some(paths);
+    }
+
+    let options = SyncOptions
+    {
+        local_path: local_path.to_owned(),
+        bucket_name: bucket_name.to_owned(),
+        checksums_name: checksums_name.to_owned(),
+        upload_options: upload_options,
+        single_file: single_file_path,
+        listed_paths: listed_paths,
+        ignored_directories: ignored_directories,
+        ignore_patterns: ignore_patterns,
+        include_patterns: include_patterns,
+        only_extensions: only_extensions,
+        exclude_extensions: exclude_extensions,
+        sensitive_patterns: sensitive_patterns,
+        allow_sensitive: parameters.iter().any(|parameter| parameter == "--i-know"),
+        follow_symlinks: parameters.iter().any(|parameter| parameter == "--follow-symlinks"),
+        skip_hidden: parameters.iter().any(|parameter| parameter == "--skip-hidden"),
+        create_dir_placeholders: create_dir_placeholders,
+        max_file_size: max_file_size,
+        since: since,
+        checksums_local_path: checksums_local_path,
+        memory_budget: memory_budget,
+        concurrency: concurrency,
+        only_new: only_new,
+        only_modified: only_modified,
+        delete: delete,
+        prune_checksums: prune_checksums,
+        delete_orphan_checksums: delete_orphan_checksums,
+        dry_run: dry_run,
+        status: status,
+        output_format: output_format,
+        verbose: verbose
+    };
+
+    let hooks = PromptHooks { assume_yes: assume_yes, confirm_threshold: confirm_threshold, output_format: output_format };
+
+    let result = match sync(&client, &options, &hooks)
+    {
+        Ok(result) => result,
+        Err(error) =>
+        {
+            print_error(&error.to_string());
+            process::exit(match error { SyncError::Usage(_) => EXIT_USAGE, SyncError::Failure(_) => EXIT_FAILURE });
+        }
+    };
+
+    let mut report = result.report;
+
+    // The prune modes only report the checksums entries they removed
+    if prune_checksums || delete_orphan_checksums
+    {
+        if output_format == OutputFormat::Json
+        {
+            let pruned: Vec<String> = report.removed_paths.iter().map(|path| json_string(path)).collect();
+            let errors: Vec<String> = report.errors.iter().map(|error| json_string(error)).collect();
+
+            println!("{{");
+            println!("  \"bucket\": {},", json_string(&bucket_name));
+            println!("  \"dry_run\": {},", dry_run);
+            println!("  \"pruned\": [{}],", pruned.join(", "));
+            println!("  \"errors\": [{}]", errors.join(", "));
+            println!("}}");
+        }
+        else if output_format == OutputFormat::Quiet
+        {
+            for error in &report.errors
+            {
+                print_error(&format!("Error: {}", error));
+            }
+        }
+
+        if !report.errors.is_empty()
+        {
+            process::exit(EXIT_FAILURE);
+        }
+
+        return;
+    }
+
+    let progress_output = output_format != OutputFormat::Quiet;
+
+    if result.confirmed
+    {
+        // Invalidate the uploaded and deleted keys so CloudFront stops serving the cached objects if the cloudfront_distribution parameter is set
+        if let Some(ref distribution_id) = cloudfront_distribution
+        {
+            if !result.changed_keys.is_empty()
+            {
+                let paths = cloudfront_invalidation_paths(&result.changed_keys, &options.upload_options.prefix);
+                let result = create_cloudfront_invalidation(distribution_id, &paths, &credentials_path, &profile, &assume_role, &http_options);
+
+                if progress_output
+                {
+                    match result
+                    {
+                        Ok(ref invalidation_id) => eprintln!("{} {} path(s) on CloudFront distribution \"{}\"... {} (invalidation {})", stderr_paint(Yellow, "Invalidating"), paths.len(), distribution_id, stderr_paint(Green, "DONE"), invalidation_id),
+                        Err(ref error) => eprintln!("{} {} path(s) on CloudFront distribution \"{}\"... {}: {}", stderr_paint(Yellow, "Invalidating"), paths.len(), distribution_id, stderr_paint(Red, "Error"), error)
+                    }
+                }
+
+                match result
+                {
+                    Ok(invalidation_id) => report.invalidation_id = Some(invalidation_id),
+                    Err(error) => report.errors.push(format!("could not invalidate the CloudFront distribution \"{}\": {}", distribution_id, error))
+                }
+            }
+        }
+
+        if progress_output
+        {
+            if report.failed_files.len() > 0
+            {
+                eprintln!("\n{}", stderr_paint(Red, format!("UPLOAD COMPLETE WITH {} FAILED FILE(S)", report.failed_files.len())));
+
+                for file in &report.failed_files
+                {
+                    eprintln!("{}: {}", stderr_paint(Red, "Failed"), local_file_get_relative_path(file, &local_path));
+                }
+            }
+            else if !result.checksums_uploaded
+            {
+                eprintln!("\n{}", stderr_paint(Red, "UPLOAD INCOMPLETE - the checksums could not be uploaded"))
+            }
+            else
+            {
+                eprintln!("\n{}", stderr_paint(Green, "UPLOAD COMPLETE"))
+            }
+
+            print_run_summary(&report, result.deleted_count);
+        }
+    }
+
+    if output_format == OutputFormat::Quiet
+    {
+        for error in &report.errors
+        {
+            print_error(&format!("Error: {}", error));
+        }
+    }
+    else if output_format == OutputFormat::Json
+    {
+        print_json_report(&report, &local_path, &bucket_name, &options.upload_options, dry_run || status);
+    }
+
+    if report.failed_files.len() > 0 || report.errors.len() > 0
     {
-        println!("\n{}", Yellow.paint("No pending modified/new files"));
+        process::exit(EXIT_FAILURE);
     }
 }